@@ -0,0 +1,171 @@
+use std::borrow::Cow;
+
+use crate::{AsepriteError, AsepriteFile, Cel, Image, Tag};
+
+/// Plays the frames of a [Tag] over time, following its direction, repeat
+/// count and the durations of its frames.
+///
+/// Time is measured in milliseconds. Tags that repeat forever loop, and
+/// other tags play once and then stop on their last frame; see
+/// [AnimationPlayer::set_looping] to change that.
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer<'a> {
+    file: &'a AsepriteFile,
+    tag: &'a Tag,
+    // The frames to play and their durations, from Tag::frame_sequence.
+    frames: Vec<(usize, u16)>,
+    looping: bool,
+    speed: f64,
+    position: usize,
+    elapsed: f64,
+    started: bool,
+    finished: bool,
+}
+
+/// Something that happened while an [AnimationPlayer] was advancing.
+#[derive(Debug, Clone, Copy)]
+pub enum AnimationEvent<'a> {
+    /// The animation started, or started over, and its tag has user data.
+    Tag(&'a Tag),
+    /// The player entered frame `frame`, which has a cel with user data.
+    Cel { frame: usize, cel: &'a Cel },
+}
+
+impl<'a> AnimationPlayer<'a> {
+    /// Creates a player for the first tag named `tag`, starting on its first
+    /// frame. Returns None if there is no such tag.
+    pub fn new(file: &'a AsepriteFile, tag: &str) -> Option<Self> {
+        let tag = file.tag_by_name(tag)?;
+        Some(AnimationPlayer {
+            file,
+            tag,
+            frames: file.tag_frames(tag),
+            looping: tag.repeat == 0,
+            speed: 1.0,
+            position: 0,
+            elapsed: 0.0,
+            started: false,
+            finished: false,
+        })
+    }
+
+    pub fn tag(&self) -> &'a Tag {
+        self.tag
+    }
+
+    /// The index of the frame being shown.
+    pub fn current_frame(&self) -> usize {
+        self.frames.get(self.position).map_or(0, |&(f, _)| f)
+    }
+
+    /// Renders the frame being shown. See [AsepriteFile::frame_image].
+    pub fn current_image(&self) -> Result<Cow<'a, Image>, AsepriteError> {
+        self.file.frame_image(self.current_frame())
+    }
+
+    /// Whether a one-shot animation has played to the end. Looping animations
+    /// never finish.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Makes the animation loop, or play once. A looping tag with a repeat
+    /// count loops all of its repeats.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+        if looping {
+            self.finished = false;
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Scales how fast time passes: 2.0 plays twice as fast. Negative speeds
+    /// are treated as 0.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.max(0.0);
+    }
+
+    /// Goes back to the first frame, as if the player was just created.
+    pub fn reset(&mut self) {
+        self.position = 0;
+        self.elapsed = 0.0;
+        self.started = false;
+        self.finished = false;
+    }
+
+    /// Moves the animation forward by `dt` milliseconds, and returns what
+    /// happened along the way. The events of the first frame are returned by
+    /// the first call.
+    ///
+    /// A `dt` that isn't a finite number is treated as 0. When a looping
+    /// animation goes around several times in one call, only the events of one
+    /// time around are returned for the loops in between.
+    pub fn advance(&mut self, dt: f64) -> Vec<AnimationEvent<'a>> {
+        let mut events = Vec::new();
+        if !self.started {
+            self.started = true;
+            self.enter(&mut events);
+        }
+        // Without any time to spend on frames, there's nowhere to go.
+        if self.finished || self.frames.iter().all(|&(_, d)| d == 0) {
+            return events;
+        }
+
+        let dt = dt * self.speed;
+        if dt.is_finite() {
+            self.elapsed += dt;
+        }
+        if self.looping {
+            // Going around a whole loop ends up on the same frame.
+            let total: f64 = self.frames.iter().map(|&(_, d)| f64::from(d)).sum();
+            if self.elapsed >= total {
+                self.elapsed %= total;
+                let position = self.position;
+                for _ in 0..self.frames.len() {
+                    self.position = (self.position + 1) % self.frames.len();
+                    self.enter(&mut events);
+                }
+                debug_assert_eq!(self.position, position);
+            }
+        }
+        loop {
+            let duration = f64::from(self.frames[self.position].1);
+            if self.elapsed < duration {
+                break;
+            }
+            if self.position + 1 < self.frames.len() {
+                self.position += 1;
+            } else if self.looping {
+                self.position = 0;
+            } else {
+                self.finished = true;
+                self.elapsed = 0.0;
+                break;
+            }
+            self.elapsed -= duration;
+            self.enter(&mut events);
+        }
+        events
+    }
+
+    fn enter(&self, events: &mut Vec<AnimationEvent<'a>>) {
+        if self.position == 0 && !self.tag.user_data.is_empty() {
+            events.push(AnimationEvent::Tag(self.tag));
+        }
+        let frame = self.current_frame();
+        if let Some(f) = self.file.frame(frame) {
+            for cel in f.cels() {
+                if !cel.user_data.is_empty() {
+                    events.push(AnimationEvent::Cel { frame, cel });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::io::{Read, Seek};
+
+use crate::{
+    mul_un8,
+    parser::{Parse, Parser},
+    AsepriteError,
+};
+
+/// How a layer is combined with the layers below it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+    Addition,
+    Subtract,
+    Divide,
+    /// A blend mode this crate doesn't know about. These are composited as
+    /// [BlendMode::Normal].
+    Unknown(u16),
+}
+
+impl From<u16> for BlendMode {
+    fn from(v: u16) -> Self {
+        match v {
+            0 => BlendMode::Normal,
+            1 => BlendMode::Multiply,
+            2 => BlendMode::Screen,
+            3 => BlendMode::Overlay,
+            4 => BlendMode::Darken,
+            5 => BlendMode::Lighten,
+            6 => BlendMode::ColorDodge,
+            7 => BlendMode::ColorBurn,
+            8 => BlendMode::HardLight,
+            9 => BlendMode::SoftLight,
+            10 => BlendMode::Difference,
+            11 => BlendMode::Exclusion,
+            12 => BlendMode::Hue,
+            13 => BlendMode::Saturation,
+            14 => BlendMode::Color,
+            15 => BlendMode::Luminosity,
+            16 => BlendMode::Addition,
+            17 => BlendMode::Subtract,
+            18 => BlendMode::Divide,
+            v => BlendMode::Unknown(v),
+        }
+    }
+}
+
+impl Parse for BlendMode {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        Ok(u16::parse(p)?.into())
+    }
+}
+
+impl BlendMode {
+    // Combines the color of the backdrop b with the color of the source s,
+    // ignoring alpha. The result still needs to be composited over the
+    // backdrop. Adapted from Aseprite.
+    pub(crate) fn apply(self, b: [i32; 3], s: [i32; 3]) -> [i32; 3] {
+        match self {
+            BlendMode::Normal | BlendMode::Unknown(_) => s,
+            BlendMode::Multiply => per_channel(b, s, multiply),
+            BlendMode::Screen => per_channel(b, s, screen),
+            BlendMode::Overlay => per_channel(b, s, |b, s| hard_light(s, b)),
+            BlendMode::Darken => per_channel(b, s, i32::min),
+            BlendMode::Lighten => per_channel(b, s, i32::max),
+            BlendMode::ColorDodge => per_channel(b, s, color_dodge),
+            BlendMode::ColorBurn => per_channel(b, s, color_burn),
+            BlendMode::HardLight => per_channel(b, s, hard_light),
+            BlendMode::SoftLight => per_channel(b, s, soft_light),
+            BlendMode::Difference => per_channel(b, s, |b, s| (b - s).abs()),
+            BlendMode::Exclusion => per_channel(b, s, |b, s| b + s - 2 * mul_un8(b, s)),
+            BlendMode::Addition => per_channel(b, s, |b, s| (b + s).min(255)),
+            BlendMode::Subtract => per_channel(b, s, |b, s| (b - s).max(0)),
+            BlendMode::Divide => per_channel(b, s, divide),
+            BlendMode::Hue => {
+                let (b, s) = (to_unit(b), to_unit(s));
+                from_unit(set_lum(set_sat(s, sat(b)), lum(b)))
+            }
+            BlendMode::Saturation => {
+                let (b, s) = (to_unit(b), to_unit(s));
+                from_unit(set_lum(set_sat(b, sat(s)), lum(b)))
+            }
+            BlendMode::Color => {
+                let (b, s) = (to_unit(b), to_unit(s));
+                from_unit(set_lum(s, lum(b)))
+            }
+            BlendMode::Luminosity => {
+                let (b, s) = (to_unit(b), to_unit(s));
+                from_unit(set_lum(b, lum(s)))
+            }
+        }
+    }
+}
+
+fn per_channel(b: [i32; 3], s: [i32; 3], f: impl Fn(i32, i32) -> i32) -> [i32; 3] {
+    [f(b[0], s[0]), f(b[1], s[1]), f(b[2], s[2])]
+}
+
+// Aseprite's equivalent of mul_un8 for division.
+fn div_un8(a: i32, b: i32) -> i32 {
+    (a * 0xff + b / 2) / b
+}
+
+fn multiply(b: i32, s: i32) -> i32 {
+    mul_un8(b, s)
+}
+
+fn screen(b: i32, s: i32) -> i32 {
+    b + s - mul_un8(b, s)
+}
+
+fn hard_light(b: i32, s: i32) -> i32 {
+    if s < 128 {
+        multiply(b, s << 1)
+    } else {
+        screen(b, (s << 1) - 255)
+    }
+}
+
+fn color_dodge(b: i32, s: i32) -> i32 {
+    if b == 0 {
+        return 0;
+    }
+    let s = 255 - s;
+    if b >= s {
+        255
+    } else {
+        div_un8(b, s)
+    }
+}
+
+fn color_burn(b: i32, s: i32) -> i32 {
+    if b == 255 {
+        return 255;
+    }
+    let b = 255 - b;
+    if b >= s {
+        0
+    } else {
+        255 - div_un8(b, s)
+    }
+}
+
+fn soft_light(b: i32, s: i32) -> i32 {
+    let b = b as f64 / 255.0;
+    let s = s as f64 / 255.0;
+    let d = if b <= 0.25 {
+        ((16.0 * b - 12.0) * b + 4.0) * b
+    } else {
+        b.sqrt()
+    };
+    let r = if s <= 0.5 {
+        b - (1.0 - 2.0 * s) * b * (1.0 - b)
+    } else {
+        b + (2.0 * s - 1.0) * (d - b)
+    };
+    (r * 255.0 + 0.5) as i32
+}
+
+fn divide(b: i32, s: i32) -> i32 {
+    if b == 0 {
+        0
+    } else if b >= s {
+        255
+    } else {
+        div_un8(b, s)
+    }
+}
+
+// The non-separable modes work on colors in the range [0, 1].
+
+fn to_unit(c: [i32; 3]) -> [f64; 3] {
+    c.map(|c| c as f64 / 255.0)
+}
+
+fn from_unit(c: [f64; 3]) -> [i32; 3] {
+    c.map(|c| (c * 255.0) as i32)
+}
+
+fn lum(c: [f64; 3]) -> f64 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn sat(c: [f64; 3]) -> f64 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn clip_color(c: [f64; 3]) -> [f64; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    let mut c = c;
+    if n < 0.0 {
+        c = c.map(|c| l + (c - l) * l / (l - n));
+    }
+    if x > 1.0 {
+        c = c.map(|c| l + (c - l) * (1.0 - l) / (x - l));
+    }
+    c
+}
+
+fn set_lum(c: [f64; 3], l: f64) -> [f64; 3] {
+    let d = l - lum(c);
+    clip_color(c.map(|c| c + d))
+}
+
+fn set_sat(c: [f64; 3], s: f64) -> [f64; 3] {
+    let mut order = [0, 1, 2];
+    order.sort_by(|&i, &j| c[i].total_cmp(&c[j]));
+    let [min, mid, max] = order;
+
+    let mut r = [0.0; 3];
+    if c[max] > c[min] {
+        r[mid] = (c[mid] - c[min]) * s / (c[max] - c[min]);
+        r[max] = s;
+    }
+    r
+}
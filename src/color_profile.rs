@@ -0,0 +1,328 @@
+use std::io::{Read, Seek};
+
+use crate::{
+    constants,
+    metadata::parse_fixed,
+    parser::{Parse, Parser, Skip},
+    AsepriteError, Image,
+};
+
+/// The color space that the colors of the sprite are in.
+#[derive(Debug)]
+pub struct ColorProfile {
+    pub profile_type: u16,
+    pub flags: u16,
+    /// Only meaningful if the gamma flag is set: see [ColorProfile::fixed_gamma].
+    pub gamma: f64,
+    _skip: Skip<8>,
+    /// The embedded ICC profile, for ICC color profiles.
+    pub icc: Option<Vec<u8>>,
+}
+
+impl ColorProfile {
+    pub fn is_srgb(&self) -> bool {
+        self.profile_type == constants::ASE_FILE_SRGB_COLOR_PROFILE
+    }
+
+    pub fn is_icc(&self) -> bool {
+        self.profile_type == constants::ASE_FILE_ICC_COLOR_PROFILE
+    }
+
+    /// The gamma the colors are encoded with instead of the usual sRGB curve,
+    /// if there is one. A gamma of 1.0 means the colors are linear.
+    pub fn fixed_gamma(&self) -> Option<f64> {
+        if self.flags & constants::ASE_COLOR_PROFILE_FLAG_GAMMA != 0 {
+            Some(self.gamma)
+        } else {
+            None
+        }
+    }
+
+    /// Converts an image in this color space to sRGB. Only ICC profiles made
+    /// of a matrix and three tone curves are supported, which covers the
+    /// common RGB working spaces.
+    pub fn to_srgb(&self, image: &Image) -> Result<Image, AsepriteError> {
+        let transform = if self.is_icc() {
+            let icc = self.icc.as_deref().unwrap_or_default();
+            Transform::from_icc(icc)?
+        } else if let Some(gamma) = self.fixed_gamma().filter(|_| self.is_srgb()) {
+            Transform::Gamma(gamma)
+        } else {
+            // Anything else is sRGB already.
+            return Ok(image.clone());
+        };
+        Ok(transform.apply(image))
+    }
+}
+
+impl Parse for ColorProfile {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        let mut profile = ColorProfile {
+            profile_type: p.next()?,
+            flags: p.next()?,
+            gamma: parse_fixed(p)?,
+            _skip: p.next()?,
+            icc: None,
+        };
+        if profile.is_icc() {
+            let len: u32 = p.next()?;
+            profile.icc = Some(p.next_n(len.try_into()?)?.to_vec());
+        }
+        Ok(profile)
+    }
+}
+
+// A tone curve from an ICC profile, mapping encoded values to linear ones.
+#[derive(Debug)]
+enum Curve {
+    Gamma(f64),
+    Table(Vec<f64>),
+    // The ICC parametric curve, always in its most general form:
+    // Y = (aX + b)^g + e for X >= d, and Y = cX + f otherwise.
+    Parametric {
+        g: f64,
+        a: f64,
+        b: f64,
+        c: f64,
+        d: f64,
+        e: f64,
+        f: f64,
+    },
+}
+
+impl Curve {
+    fn eval(&self, x: f64) -> f64 {
+        match self {
+            Curve::Gamma(g) => x.powf(*g),
+            Curve::Table(t) => {
+                let pos = x * (t.len() - 1) as f64;
+                let i = (pos.floor() as usize).min(t.len() - 2);
+                let frac = pos - i as f64;
+                t[i] + (t[i + 1] - t[i]) * frac
+            }
+            Curve::Parametric {
+                g,
+                a,
+                b,
+                c,
+                d,
+                e,
+                f,
+            } => {
+                if x >= *d {
+                    (a * x + b).max(0.0).powf(*g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Transform {
+    Gamma(f64),
+    // Tone curves for each channel, then a matrix from linear RGB to linear
+    // sRGB.
+    Matrix(Box<[Curve; 3]>, [[f64; 3]; 3]),
+}
+
+// Converts from the D50 XYZ of ICC profiles to linear sRGB.
+const XYZ_D50_TO_SRGB: [[f64; 3]; 3] = [
+    [3.1338561, -1.6168667, -0.4906146],
+    [-0.9787684, 1.9161415, 0.0334540],
+    [0.0719453, -0.2289914, 1.4052427],
+];
+
+impl Transform {
+    fn from_icc(icc: &[u8]) -> Result<Self, AsepriteError> {
+        let icc = Icc(icc);
+        let mut to_xyz = [[0.0; 3]; 3];
+        for (col, sig) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().enumerate() {
+            let tag = icc.tag(sig)?;
+            if tag.get(..4) != Some(b"XYZ ") {
+                return Err(unsupported_icc());
+            }
+            for (row, xyz) in to_xyz.iter_mut().enumerate() {
+                xyz[col] = Icc(tag).s15f16(8 + row * 4)?;
+            }
+        }
+        let curves = [
+            icc.curve(b"rTRC")?,
+            icc.curve(b"gTRC")?,
+            icc.curve(b"bTRC")?,
+        ];
+
+        let mut matrix = [[0.0; 3]; 3];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..3).map(|k| XYZ_D50_TO_SRGB[i][k] * to_xyz[k][j]).sum();
+            }
+        }
+        Ok(Transform::Matrix(Box::new(curves), matrix))
+    }
+
+    fn apply(&self, image: &Image) -> Image {
+        let mut result = Image::new(image.width, image.height);
+        // Decoding is per channel, so it can be done up front for each of the
+        // 256 values.
+        let decode: [Vec<f64>; 3] = [0, 1, 2].map(|c| {
+            (0..=255)
+                .map(|v| {
+                    let x = f64::from(v) / 255.0;
+                    match self {
+                        Transform::Gamma(g) => x.powf(*g),
+                        Transform::Matrix(curves, _) => curves[c].eval(x),
+                    }
+                })
+                .collect()
+        });
+        for (src, dst) in image
+            .data
+            .chunks_exact(4)
+            .zip(result.data.chunks_exact_mut(4))
+        {
+            let linear = [0, 1, 2].map(|c| decode[c][src[c] as usize]);
+            let linear = match self {
+                Transform::Matrix(_, m) => {
+                    [0, 1, 2].map(|i| (0..3).map(|k| m[i][k] * linear[k]).sum::<f64>())
+                }
+                _ => linear,
+            };
+            for c in 0..3 {
+                dst[c] = (srgb_encode(linear[c].clamp(0.0, 1.0)) * 255.0).round() as u8;
+            }
+            dst[3] = src[3];
+        }
+        result
+    }
+}
+
+fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn unsupported_icc() -> AsepriteError {
+    AsepriteError::Unimplemented(
+        "only ICC profiles made of a matrix and tone curves are supported".into(),
+    )
+}
+
+// Just enough of an ICC profile reader to find the tags we need. All numbers
+// in ICC profiles are big-endian.
+struct Icc<'a>(&'a [u8]);
+
+impl<'a> Icc<'a> {
+    fn bytes<const N: usize>(&self, at: usize) -> Result<[u8; N], AsepriteError> {
+        self.0
+            .get(at..at + N)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| AsepriteError::CorruptFile("ICC profile is truncated".into()))
+    }
+
+    fn u16(&self, at: usize) -> Result<u16, AsepriteError> {
+        Ok(u16::from_be_bytes(self.bytes(at)?))
+    }
+
+    fn u32(&self, at: usize) -> Result<u32, AsepriteError> {
+        Ok(u32::from_be_bytes(self.bytes(at)?))
+    }
+
+    fn s15f16(&self, at: usize) -> Result<f64, AsepriteError> {
+        Ok(f64::from(i32::from_be_bytes(self.bytes(at)?)) / 65536.0)
+    }
+
+    fn tag(&self, sig: &[u8; 4]) -> Result<&'a [u8], AsepriteError> {
+        let count = self.u32(128)?;
+        for i in 0..count as usize {
+            let entry = 132 + i * 12;
+            if &self.bytes::<4>(entry)? == sig {
+                let offset = self.u32(entry + 4)? as usize;
+                let size = self.u32(entry + 8)? as usize;
+                return self.0.get(offset..offset + size).ok_or_else(|| {
+                    AsepriteError::CorruptFile("ICC profile tag is out of bounds".into())
+                });
+            }
+        }
+        Err(unsupported_icc())
+    }
+
+    fn curve(&self, sig: &[u8; 4]) -> Result<Curve, AsepriteError> {
+        let tag = Icc(self.tag(sig)?);
+        match &tag.bytes::<4>(0)? {
+            b"curv" => {
+                let n = tag.u32(8)? as usize;
+                match n {
+                    0 => Ok(Curve::Gamma(1.0)),
+                    1 => Ok(Curve::Gamma(f64::from(tag.u16(12)?) / 256.0)),
+                    _ => (0..n)
+                        .map(|i| Ok(f64::from(tag.u16(12 + i * 2)?) / 65535.0))
+                        .collect::<Result<_, _>>()
+                        .map(Curve::Table),
+                }
+            }
+            b"para" => {
+                let function = tag.u16(8)?;
+                let nparams = match function {
+                    0 => 1,
+                    1 => 3,
+                    2 => 4,
+                    3 => 5,
+                    4 => 7,
+                    _ => return Err(unsupported_icc()),
+                };
+                let mut p = [0.0; 7];
+                for (i, v) in p.iter_mut().enumerate().take(nparams) {
+                    *v = tag.s15f16(12 + i * 4)?;
+                }
+                let [g, a, b, c, d, e, f] = p;
+                Ok(match function {
+                    0 => Curve::Gamma(g),
+                    // Y = (aX + b)^g for X >= -b/a, and 0 otherwise.
+                    1 => Curve::Parametric {
+                        g,
+                        a,
+                        b,
+                        c: 0.0,
+                        d: -b / a,
+                        e: 0.0,
+                        f: 0.0,
+                    },
+                    // Y = (aX + b)^g + c for X >= -b/a, and c otherwise.
+                    2 => Curve::Parametric {
+                        g,
+                        a,
+                        b,
+                        c: 0.0,
+                        d: -b / a,
+                        e: c,
+                        f: c,
+                    },
+                    3 => Curve::Parametric {
+                        g,
+                        a,
+                        b,
+                        c,
+                        d,
+                        e: 0.0,
+                        f: 0.0,
+                    },
+                    _ => Curve::Parametric {
+                        g,
+                        a,
+                        b,
+                        c,
+                        d,
+                        e,
+                        f,
+                    },
+                })
+            }
+            _ => Err(unsupported_icc()),
+        }
+    }
+}
@@ -11,14 +11,17 @@ pub const LAYER_REFERENCE: u16 = 1 << 6;
 pub const ASE_FILE_MAGIC: u16 = 0xA5E0;
 pub const ASE_FILE_FRAME_MAGIC: u16 = 0xF1FA;
 
-pub const ASE_FILE_FLAG_LAYER_WITH_OPACITY: u16 = 1;
+pub const ASE_FILE_FLAG_LAYER_WITH_OPACITY: u32 = 1;
+pub const ASE_FILE_FLAG_GROUP_WITH_OPACITY: u32 = 2;
+pub const ASE_FILE_FLAG_LAYERS_HAVE_UUID: u32 = 4;
 
-pub const ASE_FILE_CHUNK_FLI_COLOR2: u16 = 4;
-pub const ASE_FILE_CHUNK_FLI_COLOR: u16 = 11;
+pub const ASE_FILE_CHUNK_FLI_COLOR2: u16 = 0x0004;
+pub const ASE_FILE_CHUNK_FLI_COLOR: u16 = 0x0011;
 pub const ASE_FILE_CHUNK_LAYER: u16 = 0x2004;
 pub const ASE_FILE_CHUNK_CEL: u16 = 0x2005;
 pub const ASE_FILE_CHUNK_CEL_EXTRA: u16 = 0x2006;
 pub const ASE_FILE_CHUNK_COLOR_PROFILE: u16 = 0x2007;
+pub const ASE_FILE_CHUNK_EXTERNAL_FILES: u16 = 0x2008;
 pub const ASE_FILE_CHUNK_MASK: u16 = 0x2016;
 pub const ASE_FILE_CHUNK_PATH: u16 = 0x2017;
 pub const ASE_FILE_CHUNK_TAGS: u16 = 0x2018;
@@ -30,10 +33,22 @@ pub const ASE_FILE_CHUNK_TILESET: u16 = 0x2023;
 
 pub const ASE_FILE_LAYER_IMAGE: u16 = 0;
 pub const ASE_FILE_LAYER_GROUP: u16 = 1;
+pub const ASE_FILE_LAYER_TILEMAP: u16 = 2;
+
+pub const ASE_ANI_DIR_FORWARD: u8 = 0;
+pub const ASE_ANI_DIR_REVERSE: u8 = 1;
+pub const ASE_ANI_DIR_PING_PONG: u8 = 2;
+pub const ASE_ANI_DIR_PING_PONG_REVERSE: u8 = 3;
 
 pub const ASE_FILE_RAW_CEL: u16 = 0;
 pub const ASE_FILE_LINK_CEL: u16 = 1;
 pub const ASE_FILE_COMPRESSED_CEL: u16 = 2;
+pub const ASE_FILE_COMPRESSED_TILEMAP: u16 = 3;
+
+pub const ASE_FILE_EXTERNAL_PALETTE: u8 = 0;
+pub const ASE_FILE_EXTERNAL_TILESET: u8 = 1;
+pub const ASE_FILE_EXTERNAL_PROPERTIES_EXTENSION: u8 = 2;
+pub const ASE_FILE_EXTERNAL_TILE_MANAGEMENT_EXTENSION: u8 = 3;
 
 pub const ASE_FILE_NO_COLOR_PROFILE: u16 = 0;
 pub const ASE_FILE_SRGB_COLOR_PROFILE: u16 = 1;
@@ -45,8 +60,13 @@ pub const ASE_PALETTE_FLAG_HAS_NAME: u16 = 1;
 
 pub const ASE_USER_DATA_FLAG_HAS_TEXT: u32 = 1;
 pub const ASE_USER_DATA_FLAG_HAS_COLOR: u32 = 2;
+pub const ASE_USER_DATA_FLAG_HAS_PROPERTIES: u32 = 4;
+
+pub const ASE_CEL_EXTRA_FLAG_PRECISE_BOUNDS: u32 = 1;
 
-pub const ASE_CEL_EXTRA_FLAG_PRECISE_BOUNDS: u16 = 1;
+pub const ASE_TILESET_FLAG_EXTERNAL_FILE: u32 = 1;
+pub const ASE_TILESET_FLAG_EMBEDDED: u32 = 2;
+pub const ASE_TILESET_FLAG_ZERO_IS_NOTILE: u32 = 4;
 
 pub const ASE_SLICE_FLAG_HAS_CENTER_BOUNDS: u32 = 1;
 pub const ASE_SLICE_FLAG_HAS_PIVOT_POINT: u32 = 2;
@@ -0,0 +1,53 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::{AsepriteError, AsepriteFile, ExternalFile, Palette};
+
+/// Loads the files that an [AsepriteFile] refers to in its external files
+/// chunk: external tilesets and palettes. See
+/// [AsepriteFile::load_with_resolver].
+///
+/// This is implemented for closures, so a resolver can be as simple as
+/// `|file: &ExternalFile| ...`.
+pub trait ExternalFileResolver {
+    fn resolve(&mut self, file: &ExternalFile) -> Result<AsepriteFile, AsepriteError>;
+
+    /// Loads an external palette. By default this takes the palette of the
+    /// file from [ExternalFileResolver::resolve]; override it to read other
+    /// palette formats.
+    fn resolve_palette(&mut self, file: &ExternalFile) -> Result<Palette, AsepriteError> {
+        Ok(self.resolve(file)?.palette)
+    }
+}
+
+impl<F> ExternalFileResolver for F
+where
+    F: FnMut(&ExternalFile) -> Result<AsepriteFile, AsepriteError>,
+{
+    fn resolve(&mut self, file: &ExternalFile) -> Result<AsepriteFile, AsepriteError> {
+        self(file)
+    }
+}
+
+/// Resolves external files relative to a directory, which is usually the one
+/// containing the main file.
+#[derive(Debug, Clone)]
+pub struct DirectoryResolver {
+    dir: PathBuf,
+}
+
+impl DirectoryResolver {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        DirectoryResolver {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl ExternalFileResolver for DirectoryResolver {
+    fn resolve(&mut self, file: &ExternalFile) -> Result<AsepriteFile, AsepriteError> {
+        AsepriteFile::load(File::open(self.dir.join(&file.name))?)
+    }
+}
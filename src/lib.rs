@@ -1,18 +1,39 @@
 use std::{
+    borrow::Cow,
     error::Error,
     fmt::Display,
     io::{Read, Seek},
+    sync::OnceLock,
 };
 
-use constants::{ASE_USER_DATA_FLAG_HAS_COLOR, ASE_USER_DATA_FLAG_HAS_TEXT};
-
 use crate::parser::{Parse, Parser};
 
+mod animation;
+mod blend;
+mod color_profile;
 mod constants;
+mod external;
 mod metadata;
 mod parser;
+mod properties;
+mod render;
+#[cfg(feature = "tiled")]
+mod tiled;
+mod timeline;
 
-pub use metadata::{FileHeader, LayerHeader, Point, Rect, Slice, SliceKey, Tag};
+pub use animation::{AnimationEvent, AnimationPlayer};
+pub use blend::BlendMode;
+pub use color_profile::ColorProfile;
+pub use external::{DirectoryResolver, ExternalFileResolver};
+pub use metadata::{
+    AnimationDirection, CelExtra, ColorDepth, ExternalFile, ExternalTileset, FileFlags, FileHeader,
+    LayerFlags, LayerHeader, LayerType, Mask, Palette, PaletteEntry, Point, Rect, Slice, SliceKey,
+    Tag, Tile, TileGrid, Tileset, UserData,
+};
+pub use properties::{Properties, PropertyValue};
+pub use render::{LayerFilter, RenderOptions};
+#[cfg(feature = "tiled")]
+pub use tiled::{TiledExport, TiledTileset};
 
 #[derive(Debug, Copy, Clone)]
 struct Color(u32);
@@ -38,16 +59,26 @@ impl Color {
         self.0 as u8
     }
 
-    // Draw s on top of self with a given opacity. Adapted from Aseprite.
-    fn blend(&self, s: Color, opacity: u8) -> Color {
+    // Draw s on top of self with a given opacity and blend mode. Adapted from
+    // Aseprite.
+    fn blend(&self, s: Color, opacity: u8, mode: BlendMode) -> Color {
         let br: i32 = self.r().into();
         let bg: i32 = self.g().into();
         let bb: i32 = self.b().into();
         let ba: i32 = self.a().into();
-        let sr: i32 = s.r().into();
-        let sg: i32 = s.g().into();
-        let sb: i32 = s.b().into();
+        let mut sr: i32 = s.r().into();
+        let mut sg: i32 = s.g().into();
+        let mut sb: i32 = s.b().into();
         let sa: i32 = s.a().into();
+
+        if mode != BlendMode::Normal {
+            // The blended color only applies as far as there is a backdrop to
+            // blend with; over transparent pixels the source is left as-is.
+            let [r, g, b] = mode.apply([br, bg, bb], [sr, sg, sb]);
+            sr = mul_un8(sr, 255 - ba) + mul_un8(r, ba);
+            sg = mul_un8(sg, 255 - ba) + mul_un8(g, ba);
+            sb = mul_un8(sb, 255 - ba) + mul_un8(b, ba);
+        }
         let opacity: i32 = opacity.into();
         let sa: i32 = mul_un8(sa, opacity);
 
@@ -65,7 +96,7 @@ impl Color {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Image {
     pub width: u16,
     pub height: u16,
@@ -80,10 +111,44 @@ fn mul_un8(a: i32, b: i32) -> i32 {
 }
 
 impl Image {
+    /// Returns the pixel data as value/alpha pairs, the layout grayscale
+    /// sprites are stored in. Images from grayscale sprites have equal red,
+    /// green and blue channels, so this loses no information for them; for
+    /// other images only the red channel is kept.
+    pub fn to_grayscale(&self) -> Vec<u8> {
+        self.data
+            .chunks_exact(4)
+            .flat_map(|p| [p[0], p[3]])
+            .collect()
+    }
+
     fn new(width: u16, height: u16) -> Self {
         Self::new_from_data(width, height, vec![0; width as usize * height as usize * 4])
     }
 
+    // Returns a copy of this image flipped the way a tile in a tilemap can be.
+    // As in Tiled, the diagonal flip is applied first.
+    fn flipped(&self, x_flip: bool, y_flip: bool, diagonal_flip: bool) -> Image {
+        let (w, h) = if diagonal_flip {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        let mut result = Image::new(w, h);
+        let (w, h) = (w as usize, h as usize);
+        for y in 0..h {
+            for x in 0..w {
+                let sx = if x_flip { w - 1 - x } else { x };
+                let sy = if y_flip { h - 1 - y } else { y };
+                let (sx, sy) = if diagonal_flip { (sy, sx) } else { (sx, sy) };
+                let src = (sx + sy * self.width as usize) * 4;
+                let dst = (x + y * w) * 4;
+                result.data[dst..dst + 4].copy_from_slice(&self.data[src..src + 4]);
+            }
+        }
+        result
+    }
+
     fn new_from_data(width: u16, height: u16, data: Vec<u8>) -> Self {
         assert_eq!(width as usize * height as usize * 4, data.len());
         Image {
@@ -93,12 +158,61 @@ impl Image {
         }
     }
 
-    fn draw(&mut self, x: i16, y: i16, other: &Image, opacity: u8) {
+    // Expands value/alpha pairs to RGBA. Compositing the expanded images gives
+    // the same results as Aseprite's grayscale blending, since every channel
+    // is blended independently.
+    fn new_from_grayscale(width: u16, height: u16, data: &[u8]) -> Self {
+        let data = data
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect();
+        Self::new_from_data(width, height, data)
+    }
+
+    // Resolves indexed pixel data to RGBA using the palette. Pixels using
+    // transparent_index (if given) come out fully transparent, as do indices
+    // outside of the palette.
+    fn new_from_indexed(
+        width: u16,
+        height: u16,
+        data: &[u8],
+        palette: &Palette,
+        transparent_index: Option<u8>,
+    ) -> Self {
+        let mut image = Image::new(width, height);
+        for (idx, &i) in data.iter().enumerate() {
+            if Some(i) == transparent_index {
+                continue;
+            }
+            if let Some(c) = palette.get(i.into()) {
+                image.data[idx * 4..idx * 4 + 4].copy_from_slice(&[c.r, c.g, c.b, c.a]);
+            }
+        }
+        image
+    }
+
+    // Positions are i16, like cel positions, so images that don't fit in that
+    // range can't be drawn.
+    fn draw(
+        &mut self,
+        x: i16,
+        y: i16,
+        other: &Image,
+        opacity: u8,
+        mode: BlendMode,
+    ) -> Result<(), AsepriteError> {
+        if i16::try_from(other.width).is_err() || i16::try_from(other.height).is_err() {
+            return Err(AsepriteError::Unimplemented(format!(
+                "can't draw an image of {}x{}, images are limited to {} pixels per side",
+                other.width,
+                other.height,
+                i16::MAX
+            )));
+        }
         let mut idx = 0;
-        let w: i16 = other.width.try_into().unwrap();
-        let h: i16 = other.height.try_into().unwrap();
-        for y in y..y + h {
-            for x in x..x + w {
+        let (x, y) = (i32::from(x), i32::from(y));
+        for y in y..y + i32::from(other.height) {
+            for x in x..x + i32::from(other.width) {
                 self.draw_pixel(
                     x,
                     y,
@@ -109,15 +223,46 @@ impl Image {
                         other.data[idx + 3],
                     ),
                     opacity,
+                    mode,
                 );
                 idx += 4;
             }
         }
+        Ok(())
+    }
+
+    // Draws other scaled to fill the given bounds, using the nearest pixel of
+    // other for each pixel we draw.
+    fn draw_scaled(&mut self, x: f64, y: f64, w: f64, h: f64, other: &Image, opacity: u8) {
+        if w <= 0.0 || h <= 0.0 {
+            return;
+        }
+        let (ow, oh) = (f64::from(other.width), f64::from(other.height));
+        for dy in y.floor() as i32..(y + h).ceil() as i32 {
+            for dx in x.floor() as i32..(x + w).ceil() as i32 {
+                let sx = ((f64::from(dx) + 0.5 - x) * ow / w).floor();
+                let sy = ((f64::from(dy) + 0.5 - y) * oh / h).floor();
+                if sx < 0.0 || sy < 0.0 || sx >= ow || sy >= oh {
+                    continue;
+                }
+                let idx = (sx as usize + sy as usize * other.width as usize) * 4;
+                self.draw_pixel(
+                    dx,
+                    dy,
+                    Color::from_rgba(
+                        other.data[idx],
+                        other.data[idx + 1],
+                        other.data[idx + 2],
+                        other.data[idx + 3],
+                    ),
+                    opacity,
+                    BlendMode::Normal,
+                );
+            }
+        }
     }
 
-    fn draw_pixel(&mut self, x: i16, y: i16, source: Color, opacity: u8) {
-        let x: i32 = x.into();
-        let y: i32 = y.into();
+    fn draw_pixel(&mut self, x: i32, y: i32, source: Color, opacity: u8, mode: BlendMode) {
         let w: i32 = self.width.into();
         let wu: usize = self.width.into();
         let h: i32 = (self.data.len() / wu).try_into().unwrap();
@@ -126,7 +271,7 @@ impl Image {
         }
         let x: usize = x.try_into().expect("fits in a usize");
         let y: usize = y.try_into().expect("fits in a usize");
-        let w: usize = self.width.try_into().expect("fits in a usize");
+        let w: usize = self.width.into();
         let idx: usize = (x + y * w) * 4;
 
         let b = Color::from_rgba(
@@ -136,7 +281,7 @@ impl Image {
             self.data[idx + 3],
         );
 
-        let result = b.blend(source, opacity);
+        let result = b.blend(source, opacity, mode);
 
         self.data[idx] = result.r();
         self.data[idx + 1] = result.g();
@@ -145,24 +290,169 @@ impl Image {
     }
 }
 
+/// The contents of one layer in one frame.
+#[derive(Debug)]
+pub struct Cel {
+    pub layer_index: usize,
+    pub x: i16,
+    pub y: i16,
+    pub opacity: u8,
+    /// Moves the cel this many layers up (or down, if negative) when the
+    /// frame is composited.
+    pub z_index: i16,
+    pub user_data: UserData,
+    pub extra: Option<CelExtra>,
+    content: CelContent,
+    // The decoded image, if the cache is on.
+    image: OnceLock<Image>,
+}
+
+// The pixels of a cel as they're stored in the file. They're only decoded
+// when something needs the image of the cel.
+#[derive(Debug)]
+enum CelContent {
+    Raw {
+        width: u16,
+        height: u16,
+        data: Vec<u8>,
+    },
+    Compressed {
+        width: u16,
+        height: u16,
+        data: Vec<u8>,
+    },
+    // The tiles are in Frame::tile_grids.
+    Tilemap,
+    Linked(usize),
+}
+
+impl Cel {
+    pub fn user_data(&self) -> &UserData {
+        &self.user_data
+    }
+
+    /// The exact position and size of the cel in the sprite, if it has them.
+    /// Cels are drawn scaled to these bounds.
+    pub fn precise_bounds(&self) -> Option<&CelExtra> {
+        self.extra.as_ref().filter(|e| e.has_precise_bounds())
+    }
+
+    /// The frame whose cel on the same layer this cel shares its image with.
+    pub fn linked_frame(&self) -> Option<usize> {
+        match self.content {
+            CelContent::Linked(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    pub fn is_linked(&self) -> bool {
+        self.linked_frame().is_some()
+    }
+}
+
+/// The cels and timing of one frame. Images are rendered on demand: see
+/// [AsepriteFile::frame_image].
 #[derive(Debug)]
 pub struct Frame {
     pub duration: u16,
-    layers: Vec<Image>,
-    pub image: Image,
+    cels: Vec<Cel>,
+    tile_grids: Vec<Option<TileGrid>>,
+    // Index into AsepriteFile::palettes of the palette this frame uses.
+    palette: usize,
+    // The flattened image, if the cache is on.
+    image: OnceLock<Image>,
+}
+
+impl Frame {
+    /// The cels in this frame, in the order they appear in the file.
+    pub fn cels(&self) -> &[Cel] {
+        &self.cels
+    }
+
+    /// The cel of the layer at index `layer` in this frame, if it has one.
+    pub fn cel(&self, layer: usize) -> Option<&Cel> {
+        self.cels.iter().find(|c| c.layer_index == layer)
+    }
+
+    /// The tiles of the tilemap layer at index `i` in this frame, if it has
+    /// any.
+    pub fn tile_grid(&self, i: usize) -> Option<&TileGrid> {
+        self.tile_grids.get(i)?.as_ref()
+    }
+
+    /// The tiles of every tilemap layer in this frame, along with the index
+    /// of the layer.
+    pub fn tile_grids(&self) -> impl Iterator<Item = (usize, &TileGrid)> {
+        self.tile_grids
+            .iter()
+            .enumerate()
+            .filter_map(|(i, g)| Some((i, g.as_ref()?)))
+    }
+}
+
+// The object that the next user data chunk belongs to: whichever came right
+// before it.
+#[derive(Debug)]
+enum UserDataTarget {
+    None,
+    Sprite,
+    Layer(usize),
+    Cel(usize),
+    // The index of the next tag to get user data.
+    Tags(usize),
+    Slice(usize),
+    // The tileset comes first, followed by each of its tiles.
+    Tileset(usize, Option<usize>),
 }
 
 #[derive(Debug)]
 pub struct AsepriteFile {
     header: FileHeader,
+    user_data: UserData,
+    color_profile: Option<ColorProfile>,
+    palette: Palette,
+    // The palette as it was at the end of each frame that changed it.
+    palettes: Vec<Palette>,
+    ignore_old_palettes: bool,
     layers: Vec<LayerHeader>,
+    tilesets: Vec<Tileset>,
     frames: Vec<Frame>,
     tags: Vec<Tag>,
     slices: Vec<Slice>,
+    masks: Vec<Mask>,
+    external_files: Vec<ExternalFile>,
+    external_palettes: Vec<(u32, Palette)>,
+    cache: bool,
+    user_data_target: UserDataTarget,
+    // Embedded tileset pixels, decoded once the palette of their frame is
+    // known.
+    pending_tiles: Vec<(usize, Vec<u8>)>,
 }
 
 impl AsepriteFile {
+    /// Loads a file. Tilesets stored in other files are left without tiles,
+    /// and external palettes aren't loaded; see
+    /// [AsepriteFile::load_with_resolver] to load those too.
+    ///
+    /// Only the metadata is decoded up front. The pixels of cels are kept as
+    /// they are in the file until an image is asked for.
     pub fn load<R: Read + Seek>(r: R) -> Result<Self, AsepriteError> {
+        Self::load_inner(r, None)
+    }
+
+    /// Loads a file, using `resolver` to load the files it refers to: external
+    /// tilesets and palettes.
+    pub fn load_with_resolver<R: Read + Seek>(
+        r: R,
+        mut resolver: impl ExternalFileResolver,
+    ) -> Result<Self, AsepriteError> {
+        Self::load_inner(r, Some(&mut resolver))
+    }
+
+    fn load_inner<R: Read + Seek>(
+        r: R,
+        mut resolver: Option<&mut dyn ExternalFileResolver>,
+    ) -> Result<Self, AsepriteError> {
         let mut parser = Parser::new(r);
 
         let header = FileHeader::parse(&mut parser)?;
@@ -172,22 +462,304 @@ impl AsepriteFile {
 
         let mut file = AsepriteFile {
             header,
+            user_data: UserData::default(),
+            color_profile: None,
+            palette: Palette::default(),
+            palettes: Vec::new(),
+            ignore_old_palettes: false,
             layers: Vec::new(),
+            tilesets: Vec::new(),
             frames: Vec::new(),
             tags: Vec::new(),
             slices: Vec::new(),
+            masks: Vec::new(),
+            external_files: Vec::new(),
+            external_palettes: Vec::new(),
+            cache: false,
+            user_data_target: UserDataTarget::None,
+            pending_tiles: Vec::new(),
         };
 
         for _ in 0..file.header.frames {
-            file.process_next_frame(&mut parser)?;
+            file.process_next_frame(&mut parser, &mut resolver)?;
         }
 
         Ok(file)
     }
 
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// The color profile of the sprite, if the file has one. Without one,
+    /// colors should be treated as sRGB.
+    pub fn color_profile(&self) -> Option<&ColorProfile> {
+        self.color_profile.as_ref()
+    }
+
+    /// Returns the flattened image of frame `frame` converted from the color
+    /// profile of the sprite to sRGB. Images are otherwise left in the color
+    /// space of the sprite, as Aseprite stores them.
+    pub fn render_srgb(&self, frame: usize) -> Result<Image, AsepriteError> {
+        let image = self.frame_image(frame)?;
+        match &self.color_profile {
+            Some(profile) => profile.to_srgb(&image),
+            None => Ok(image.into_owned()),
+        }
+    }
+
+    /// Returns the flattened image of frame `frame`, with every visible layer
+    /// composited. Borrows from the cache if the image is in it.
+    pub fn frame_image(&self, frame: usize) -> Result<Cow<'_, Image>, AsepriteError> {
+        let cached = &self.try_frame(frame)?.image;
+        if let Some(image) = cached.get() {
+            return Ok(Cow::Borrowed(image));
+        }
+        let image = self.render_frame(frame, &RenderOptions::default())?;
+        if self.cache {
+            let _ = cached.set(image);
+            return Ok(Cow::Borrowed(cached.get().unwrap()));
+        }
+        Ok(Cow::Owned(image))
+    }
+
+    /// Turns on caching of the flattened images of frames and the decoded
+    /// images of cels, so that they're only rendered once. This is off by
+    /// default, and turning it off frees everything cached so far.
+    pub fn set_cache(&mut self, enabled: bool) {
+        self.cache = enabled;
+        if !enabled {
+            for frame in &mut self.frames {
+                frame.image = OnceLock::new();
+                for cel in &mut frame.cels {
+                    cel.image = OnceLock::new();
+                }
+            }
+        }
+    }
+
+    /// The user data of the sprite itself.
+    pub fn user_data(&self) -> &UserData {
+        &self.user_data
+    }
+
+    /// The palette of the sprite, as of the last frame. Sprites in every color
+    /// mode have a palette, but only indexed sprites depend on it for their
+    /// image data.
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// The palette used by frame `frame`. Palette chunks change the palette
+    /// from the frame they're in onwards, so this can differ from
+    /// [AsepriteFile::palette], which is the palette of the last frame.
+    pub fn frame_palette(&self, frame: usize) -> Option<&Palette> {
+        Some(&self.palettes[self.frames.get(frame)?.palette])
+    }
+
+    /// The layers of the sprite, from bottom to top. The layers form a tree
+    /// through [LayerHeader::child_level]: a group comes right before its
+    /// children, so this is a depth-first walk of that tree.
+    pub fn layers(&self) -> &[LayerHeader] {
+        &self.layers
+    }
+
+    /// The cel of layer `layer` in frame `frame`, if it has one.
+    pub fn cel(&self, frame: usize, layer: usize) -> Option<&Cel> {
+        self.frames.get(frame)?.cel(layer)
+    }
+
+    /// The image of the cel of layer `layer` in frame `frame`, trimmed to its
+    /// contents and drawn at the position of the cel. Links to other frames
+    /// are followed. Returns None if there is no such cel.
+    pub fn cel_image(&self, frame: usize, layer: usize) -> Result<Option<Image>, AsepriteError> {
+        Ok(self.decode_cel_at(frame, layer)?.map(Cow::into_owned))
+    }
+
+    /// Returns the layer with the given name, if there is one.
+    pub fn layer_by_name(&self, name: &str) -> Option<&LayerHeader> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    /// Returns the layer with the given UUID, if the file has layer UUIDs.
+    pub fn layer_by_uuid(&self, uuid: &[u8; 16]) -> Option<&LayerHeader> {
+        self.layers.iter().find(|l| l.uuid.as_ref() == Some(uuid))
+    }
+
+    /// Returns the index of the group containing the layer at index `layer`,
+    /// or None for top-level layers.
+    pub fn layer_parent(&self, layer: usize) -> Option<usize> {
+        let level = self.layers.get(layer)?.child_level.checked_sub(1)?;
+        self.layers[..layer]
+            .iter()
+            .rposition(|l| l.child_level == level)
+    }
+
+    /// Returns the indices of the direct children of the group at index
+    /// `layer`, from bottom to top. Passing None gives the top-level layers.
+    pub fn layer_children(&self, layer: Option<usize>) -> impl Iterator<Item = usize> + '_ {
+        let (start, level) = match layer {
+            Some(i) => (i + 1, self.layers.get(i).map_or(0, |l| l.child_level + 1)),
+            None => (0, 0),
+        };
+        self.layers
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take_while(move |(_, l)| l.child_level >= level)
+            .filter(move |(_, l)| l.child_level == level)
+            .map(|(i, _)| i)
+    }
+
+    /// Returns the names of the layer at index `layer` and all of its parent
+    /// groups joined with slashes, such as `"body/arm"`.
+    pub fn layer_path(&self, layer: usize) -> Option<String> {
+        let mut names = vec![self.layers.get(layer)?.name.as_str()];
+        let mut current = layer;
+        while let Some(parent) = self.layer_parent(current) {
+            names.push(&self.layers[parent].name);
+            current = parent;
+        }
+        names.reverse();
+        Some(names.join("/"))
+    }
+
+    /// Returns the index of the layer at the given path, as returned by
+    /// [AsepriteFile::layer_path].
+    pub fn layer_index_by_path(&self, path: &str) -> Option<usize> {
+        let mut current = None;
+        for name in path.split('/') {
+            current = Some(
+                self.layer_children(current)
+                    .find(|&i| self.layers[i].name == name)?,
+            );
+        }
+        current
+    }
+
+    /// Returns the layer at the given path, as returned by
+    /// [AsepriteFile::layer_path].
+    pub fn layer_by_path(&self, path: &str) -> Option<&LayerHeader> {
+        self.layers.get(self.layer_index_by_path(path)?)
+    }
+
+    pub fn tilesets(&self) -> &[Tileset] {
+        &self.tilesets
+    }
+
+    /// Returns the tileset with the given ID. Tilemap layers refer to their
+    /// tileset by this ID through [LayerHeader::tileset_index].
+    pub fn tileset_by_id(&self, id: u32) -> Option<&Tileset> {
+        self.tilesets.iter().find(|t| t.id == id)
+    }
+
+    /// Returns the tiles of the tilemap layer `layer` in frame `frame`, if it
+    /// has any there. The tile images are found through
+    /// [AsepriteFile::tileset_by_id] and the grid's `tileset_id`.
+    pub fn tile_grid(&self, frame: usize, layer: usize) -> Option<&TileGrid> {
+        self.frame(frame)?.tile_grid(layer)
+    }
+
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Returns the frame at index `i`, if there is one.
+    pub fn frame(&self, i: usize) -> Option<&Frame> {
+        self.frames.get(i)
+    }
+
+    // Like `frame`, for the functions that return a Result.
+    fn try_frame(&self, i: usize) -> Result<&Frame, AsepriteError> {
+        self.frames
+            .get(i)
+            .ok_or_else(|| AsepriteError::Error(format!("there is no frame {}", i).into()))
+    }
+
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+
+    /// Returns the first tag with the given name, if there is one.
+    pub fn tag_by_name(&self, name: &str) -> Option<&Tag> {
+        self.tags.iter().find(|t| t.name == name)
+    }
+
+    /// The frames `tag` plays, along with their durations in milliseconds.
+    /// See [Tag::frame_sequence] and [AsepriteFile::frame_duration].
+    pub fn tag_frames(&self, tag: &Tag) -> Vec<(usize, u16)> {
+        tag.frame_sequence()
+            .into_iter()
+            .filter_map(|f| Some((f, self.frame_duration(f)?)))
+            .collect()
+    }
+
+    pub fn slices(&self) -> &[Slice] {
+        &self.slices
+    }
+
+    /// Returns the first slice with the given name, if there is one.
+    pub fn slice_by_name(&self, name: &str) -> Option<&Slice> {
+        self.slices.iter().find(|s| s.name == name)
+    }
+
+    /// The slices that exist at frame `frame`, along with their key for that
+    /// frame.
+    pub fn slices_at(&self, frame: usize) -> impl Iterator<Item = (&Slice, &SliceKey)> {
+        self.slices
+            .iter()
+            .filter_map(move |s| Some((s, s.key_at(frame)?)))
+    }
+
+    /// The files and extensions this file refers to.
+    pub fn external_files(&self) -> &[ExternalFile] {
+        &self.external_files
+    }
+
+    /// Returns the external file with the given ID, if there is one.
+    pub fn external_file(&self, id: u32) -> Option<&ExternalFile> {
+        self.external_files.iter().find(|f| f.id == id)
+    }
+
+    /// Returns the palette loaded from the external file with the given ID,
+    /// if the file was loaded with a resolver. External palettes are only
+    /// listed in the file; they don't change the palette of the sprite.
+    pub fn external_palette(&self, id: u32) -> Option<&Palette> {
+        self.external_palettes
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map(|(_, p)| p)
+    }
+
+    /// The selection masks stored in the file. Only old versions of Aseprite
+    /// wrote these.
+    pub fn masks(&self) -> &[Mask] {
+        &self.masks
+    }
+
+    /// Returns the full-canvas image of layer `layer` in frame `frame`,
+    /// ignoring the opacity and visibility of the layer. Groups are empty.
+    pub fn layer_image(&self, frame: usize, layer: usize) -> Result<Image, AsepriteError> {
+        let mut image = Image::new(self.header.width, self.header.height);
+        let Some(cel) = self.try_frame(frame)?.cel(layer) else {
+            return Ok(image);
+        };
+        let Some(cel_image) = self.decode_cel_at(frame, layer)? else {
+            return Ok(image);
+        };
+        match cel.precise_bounds() {
+            Some(b) if (b.width, b.height) != (cel_image.width.into(), cel_image.height.into()) => {
+                image.draw_scaled(b.x, b.y, b.width, b.height, &cel_image, cel.opacity);
+            }
+            _ => image.draw(cel.x, cel.y, &cel_image, cel.opacity, BlendMode::Normal)?,
+        }
+        Ok(image)
+    }
+
     fn process_next_frame<R: Read + Seek>(
         &mut self,
         parser: &mut Parser<R>,
+        resolver: &mut Option<&mut dyn ExternalFileResolver>,
     ) -> Result<(), AsepriteError> {
         let _size: u32 = parser.next()?;
         let magic: u16 = parser.next()?;
@@ -198,33 +770,103 @@ impl AsepriteFile {
 
         let mut frame = Frame {
             duration,
-            layers: Vec::new(),
-            image: Image::new(self.header.width, self.header.height),
+            cels: Vec::new(),
+            tile_grids: Vec::new(),
+            palette: 0,
+            image: OnceLock::new(),
         };
+        // User data always follows a chunk of the same frame.
+        self.user_data_target = UserDataTarget::None;
 
         for _ in 0..chunks {
-            while self.layers.len() > frame.layers.len() {
-                frame
-                    .layers
-                    .push(Image::new(self.header.width, self.header.height));
-            }
-            self.apply_chunk(&mut frame, parser)?;
+            frame.tile_grids.resize(self.layers.len(), None);
+            self.apply_chunk(&mut frame, parser, resolver)?;
         }
+        frame.tile_grids.resize(self.layers.len(), None);
 
-        for (i, l) in self.layers.iter().enumerate() {
-            if l.visible() {
-                frame.image.draw(0, 0, &frame.layers[i], l.opacity);
-            }
+        if self.palettes.last() != Some(&self.palette) {
+            self.palettes.push(self.palette.clone());
+        }
+        frame.palette = self.palettes.len() - 1;
+        for (i, data) in std::mem::take(&mut self.pending_tiles) {
+            let tiles = self.decode_tiles(&self.tilesets[i], data, &self.palette)?;
+            self.tilesets[i].tiles = tiles;
         }
 
         self.frames.push(frame);
         Ok(())
     }
 
+    // Decodes the image of the cel of layer `layer` in frame `frame`,
+    // following links. Borrows from the cache if the image is in it.
+    //
+    // Linked cels share the pixels of a cel in an earlier frame, but indexed
+    // pixels still take their colors from the palette of `frame`. The image
+    // is cached on the cel it was decoded from when both frames use the same
+    // palette, and on the linked cel otherwise.
+    fn decode_cel_at(
+        &self,
+        frame: usize,
+        layer: usize,
+    ) -> Result<Option<Cow<'_, Image>>, AsepriteError> {
+        let Some(cel) = self.cel(frame, layer) else {
+            return Ok(None);
+        };
+        let (mut source_frame, mut source) = (frame, cel);
+        // Links point back to earlier frames, so this can't go around in
+        // circles.
+        while let CelContent::Linked(f) = source.content {
+            if f >= source_frame {
+                return Ok(None);
+            }
+            let Some(c) = self.cel(f, layer) else {
+                return Ok(None);
+            };
+            (source_frame, source) = (f, c);
+        }
+        let palette = self.frames[frame].palette;
+        let cache = if self.frames[source_frame].palette == palette {
+            &source.image
+        } else {
+            &cel.image
+        };
+        if let Some(image) = cache.get() {
+            return Ok(Some(Cow::Borrowed(image)));
+        }
+        let palette = &self.palettes[palette];
+        let image = match &source.content {
+            CelContent::Linked(_) => return Ok(None),
+            CelContent::Raw {
+                width,
+                height,
+                data,
+            } => self.decode_cel(layer, *width, *height, data.clone(), palette)?,
+            CelContent::Compressed {
+                width,
+                height,
+                data,
+            } => {
+                // For some reason inflate uses a String instead of an Error.
+                let data = inflate::inflate_bytes_zlib(data).map_err(AsepriteError::CorruptFile)?;
+                self.decode_cel(layer, *width, *height, data, palette)?
+            }
+            CelContent::Tilemap => match self.frames[source_frame].tile_grid(layer) {
+                Some(grid) => self.render_tile_grid(grid)?,
+                None => return Ok(None),
+            },
+        };
+        if self.cache {
+            let _ = cache.set(image);
+            return Ok(cache.get().map(Cow::Borrowed));
+        }
+        Ok(Some(Cow::Owned(image)))
+    }
+
     fn apply_chunk<R: Read + Seek>(
         &mut self,
         frame: &mut Frame,
         parser: &mut Parser<R>,
+        resolver: &mut Option<&mut dyn ExternalFileResolver>,
     ) -> Result<(), AsepriteError> {
         let chunk_pos = parser.position();
         let chunk_size: u32 = parser.next()?;
@@ -233,14 +875,27 @@ impl AsepriteFile {
         let chunk_end = chunk_pos + chunk_size;
 
         match chunk_type {
-            constants::ASE_FILE_CHUNK_COLOR_PROFILE => {
-                // TODO
-            }
+            constants::ASE_FILE_CHUNK_COLOR_PROFILE => self.color_profile = Some(parser.next()?),
             constants::ASE_FILE_CHUNK_PALETTE => {
-                // TODO
+                self.palette.apply_chunk(parser)?;
+                self.ignore_old_palettes = true;
+                // User data following the first palette belongs to the sprite.
+                if self.frames.is_empty() {
+                    self.user_data_target = UserDataTarget::Sprite;
+                }
+            }
+            constants::ASE_FILE_CHUNK_FLI_COLOR | constants::ASE_FILE_CHUNK_FLI_COLOR2 => {
+                // Files with the new palette chunk also write the old ones for
+                // backwards compatibility, but they can't hold everything the
+                // new one does.
+                if !self.ignore_old_palettes {
+                    let six_bit = chunk_type == constants::ASE_FILE_CHUNK_FLI_COLOR;
+                    self.palette.apply_old_chunk(parser, six_bit)?;
+                }
             }
-            constants::ASE_FILE_CHUNK_FLI_COLOR2 => {
-                // TODO
+            constants::ASE_FILE_CHUNK_MASK => self.masks.push(parser.next()?),
+            constants::ASE_FILE_CHUNK_PATH => {
+                // Paths were never used by Aseprite, so there's nothing to read.
             }
             constants::ASE_FILE_CHUNK_SLICE => {
                 let nkeys: u32 = parser.next()?;
@@ -272,24 +927,17 @@ impl AsepriteFile {
                 }
 
                 self.slices.push(slice);
+                self.user_data_target = UserDataTarget::Slice(self.slices.len() - 1);
             }
             constants::ASE_FILE_CHUNK_USER_DATA => {
-                // These come following the slice data.
-                let flags: u32 = parser.next()?;
-                if flags & ASE_USER_DATA_FLAG_HAS_TEXT != 0 {
-                    self.slices.last_mut().unwrap().user_data.string = parser.next()?;
-                }
-                if flags & ASE_USER_DATA_FLAG_HAS_COLOR != 0 {
-                    self.slices.last_mut().unwrap().user_data.r = parser.next()?;
-                    self.slices.last_mut().unwrap().user_data.g = parser.next()?;
-                    self.slices.last_mut().unwrap().user_data.b = parser.next()?;
-                    self.slices.last_mut().unwrap().user_data.a = parser.next()?;
-                }
+                let user_data: UserData = parser.next()?;
+                self.attach_user_data(frame, user_data);
             }
             constants::ASE_FILE_CHUNK_TAGS => {
                 let ntags: u16 = parser.next()?;
                 parser.skip(8)?;
 
+                self.user_data_target = UserDataTarget::Tags(self.tags.len());
                 for _ in 0..ntags {
                     self.tags.push(parser.next()?);
                 }
@@ -300,23 +948,48 @@ impl AsepriteFile {
                 let y: i16 = parser.next()?;
                 let opacity: u8 = parser.next()?;
                 let cel_type: u16 = parser.next()?;
-                parser.skip(7)?;
-
-                match cel_type {
-                    constants::ASE_FILE_COMPRESSED_CEL => {
-                        let w: u16 = parser.next()?;
-                        let h: u16 = parser.next()?;
-                        let data = parser.next_n(chunk_end - parser.position())?;
-                        // For some reason inflate uses a String instead of an Error.
-                        let data = inflate::inflate_bytes_zlib(data)
-                            .map_err(AsepriteError::CorruptFile)?;
-                        let cel = Image::new_from_data(w, h, data);
-                        frame.layers[layer_index as usize].draw(x, y, &cel, opacity);
+                let z_index: i16 = parser.next()?;
+                parser.skip(5)?;
+
+                if usize::from(layer_index) >= self.layers.len() {
+                    return Err(AsepriteError::CorruptFile(format!(
+                        "cel refers to missing layer {}",
+                        layer_index
+                    )));
+                }
+                let content = match cel_type {
+                    constants::ASE_FILE_RAW_CEL => CelContent::Raw {
+                        width: parser.next()?,
+                        height: parser.next()?,
+                        data: parser.next_n(chunk_end - parser.position())?.to_vec(),
+                    },
+                    constants::ASE_FILE_COMPRESSED_CEL => CelContent::Compressed {
+                        width: parser.next()?,
+                        height: parser.next()?,
+                        data: parser.next_n(chunk_end - parser.position())?.to_vec(),
+                    },
+                    constants::ASE_FILE_COMPRESSED_TILEMAP => {
+                        let tileset_id = self
+                            .layers
+                            .get(layer_index as usize)
+                            .and_then(|l| l.tileset_index)
+                            .ok_or_else(|| {
+                                AsepriteError::CorruptFile(format!(
+                                    "tilemap cel on layer {} has no tileset",
+                                    layer_index
+                                ))
+                            })?;
+                        let grid = TileGrid::parse(parser, chunk_end, x, y, tileset_id)?;
+                        frame.tile_grids[layer_index as usize] = Some(grid);
+                        CelContent::Tilemap
                     }
                     constants::ASE_FILE_LINK_CEL => {
-                        let linked_frame: u16 = parser.next()?;
-                        let cel = &self.frames[linked_frame as usize].layers[layer_index as usize];
-                        frame.layers[layer_index as usize].draw(x, y, cel, opacity);
+                        let linked: u16 = parser.next()?;
+                        frame.tile_grids[layer_index as usize] = self
+                            .frames
+                            .get(usize::from(linked))
+                            .and_then(|f| f.tile_grids.get(usize::from(layer_index))?.clone());
+                        CelContent::Linked(linked.into())
                     }
                     ct => {
                         return Err(AsepriteError::Unimplemented(format!(
@@ -324,9 +997,78 @@ impl AsepriteFile {
                             ct
                         )));
                     }
+                };
+
+                frame.cels.push(Cel {
+                    layer_index: layer_index.into(),
+                    x,
+                    y,
+                    opacity,
+                    z_index,
+                    user_data: UserData::default(),
+                    extra: None,
+                    content,
+                    image: OnceLock::new(),
+                });
+                self.user_data_target = UserDataTarget::Cel(frame.cels.len() - 1);
+            }
+            constants::ASE_FILE_CHUNK_CEL_EXTRA => {
+                // This always comes right after the cel it belongs to.
+                let extra: CelExtra = parser.next()?;
+                if let Some(cel) = frame.cels.last_mut() {
+                    cel.extra = Some(extra);
+                }
+            }
+            constants::ASE_FILE_CHUNK_EXTERNAL_FILES => {
+                let nentries: u32 = parser.next()?;
+                parser.skip(8)?;
+                for _ in 0..nentries {
+                    let file: ExternalFile = parser.next()?;
+                    if let Some(resolver) = resolver.as_mut().filter(|_| file.is_palette()) {
+                        self.external_palettes
+                            .push((file.id, resolver.resolve_palette(&file)?));
+                    }
+                    self.external_files.push(file);
                 }
             }
-            constants::ASE_FILE_CHUNK_LAYER => self.layers.push(parser.next()?),
+            constants::ASE_FILE_CHUNK_LAYER => {
+                let mut layer: LayerHeader = parser.next()?;
+                if self.header.flags.layers_have_uuid() {
+                    layer.uuid = Some(parser.next_n(16)?.try_into()?);
+                }
+                self.layers.push(layer);
+                self.user_data_target = UserDataTarget::Layer(self.layers.len() - 1);
+            }
+            constants::ASE_FILE_CHUNK_TILESET => {
+                let mut tileset: Tileset = parser.next()?;
+                if tileset.flags & constants::ASE_TILESET_FLAG_EMBEDDED != 0 {
+                    let len: u32 = parser.next()?;
+                    let data = parser.next_n(len.try_into()?)?;
+                    let data =
+                        inflate::inflate_bytes_zlib(data).map_err(AsepriteError::CorruptFile)?;
+                    // Palette chunks later in this frame still apply.
+                    self.pending_tiles.push((self.tilesets.len(), data));
+                } else if let (Some(external), Some(resolver)) =
+                    (&tileset.external, resolver.as_mut())
+                {
+                    let file = self.external_file(external.file_id).ok_or_else(|| {
+                        AsepriteError::CorruptFile(format!(
+                            "tileset {} refers to missing external file {}",
+                            tileset.id, external.file_id
+                        ))
+                    })?;
+                    let other = resolver.resolve(file)?;
+                    let source = other.tileset_by_id(external.tileset_id).ok_or_else(|| {
+                        AsepriteError::CorruptFile(format!(
+                            "external file {} has no tileset {}",
+                            file.name, external.tileset_id
+                        ))
+                    })?;
+                    tileset.tiles = source.tiles.clone();
+                }
+                self.tilesets.push(tileset);
+                self.user_data_target = UserDataTarget::Tileset(self.tilesets.len() - 1, None);
+            }
             ct => {
                 return Err(AsepriteError::Unimplemented(format!(
                     "unhandled chunk type 0x{:x}. Please open an issue including the file you're attempting to open.",
@@ -339,6 +1081,190 @@ impl AsepriteFile {
 
         Ok(())
     }
+
+    // Stores user data on the object that came before it, and moves on to the
+    // next object for the chunks that are followed by several user data
+    // chunks. User data without anything to attach to is dropped.
+    fn attach_user_data(&mut self, frame: &mut Frame, user_data: UserData) {
+        match self.user_data_target {
+            UserDataTarget::None => {}
+            UserDataTarget::Sprite => self.user_data = user_data,
+            UserDataTarget::Layer(i) => {
+                if let Some(layer) = self.layers.get_mut(i) {
+                    layer.user_data = user_data;
+                }
+            }
+            UserDataTarget::Cel(i) => {
+                if let Some(cel) = frame.cels.get_mut(i) {
+                    cel.user_data = user_data;
+                }
+            }
+            UserDataTarget::Tags(i) => {
+                if let Some(tag) = self.tags.get_mut(i) {
+                    tag.user_data = user_data;
+                    self.user_data_target = UserDataTarget::Tags(i + 1);
+                }
+            }
+            UserDataTarget::Slice(i) => {
+                if let Some(slice) = self.slices.get_mut(i) {
+                    slice.user_data = user_data;
+                }
+            }
+            UserDataTarget::Tileset(i, None) => {
+                self.tilesets[i].user_data = user_data;
+                self.user_data_target = UserDataTarget::Tileset(i, Some(0));
+            }
+            UserDataTarget::Tileset(i, Some(tile)) => {
+                let tileset = &mut self.tilesets[i];
+                if tile < tileset.tile_count as usize {
+                    tileset
+                        .tile_user_data
+                        .resize_with(tile + 1, UserData::default);
+                    tileset.tile_user_data[tile] = user_data;
+                    self.user_data_target = UserDataTarget::Tileset(i, Some(tile + 1));
+                }
+            }
+        }
+    }
+
+    // Turns the pixel data of a cel into an RGBA image, according to the color
+    // depth of the file.
+    fn decode_cel(
+        &self,
+        layer_index: usize,
+        w: u16,
+        h: u16,
+        data: Vec<u8>,
+        palette: &Palette,
+    ) -> Result<Image, AsepriteError> {
+        let layer = self.layers.get(layer_index).ok_or_else(|| {
+            AsepriteError::CorruptFile(format!("cel refers to missing layer {}", layer_index))
+        })?;
+        self.decode_image(w, h, data, palette, layer.background())
+    }
+
+    fn bytes_per_pixel(&self) -> Result<usize, AsepriteError> {
+        match self.header.depth {
+            ColorDepth::Rgba => Ok(4),
+            ColorDepth::Grayscale => Ok(2),
+            ColorDepth::Indexed => Ok(1),
+            ColorDepth::Unknown(d) => Err(AsepriteError::Unimplemented(format!(
+                "unhandled color depth {}. Please open an issue including the file you're attempting to open.",
+                d
+            ))),
+        }
+    }
+
+    // Turns pixel data in the color depth of the file into an RGBA image. The
+    // transparent index of indexed sprites doesn't apply to background layers.
+    fn decode_image(
+        &self,
+        w: u16,
+        h: u16,
+        data: Vec<u8>,
+        palette: &Palette,
+        background: bool,
+    ) -> Result<Image, AsepriteError> {
+        if data.len() != w as usize * h as usize * self.bytes_per_pixel()? {
+            return Err(AsepriteError::CorruptFile(format!(
+                "image of size {}x{} has {} bytes of pixel data",
+                w,
+                h,
+                data.len()
+            )));
+        }
+
+        if self.header.depth == ColorDepth::Grayscale {
+            Ok(Image::new_from_grayscale(w, h, &data))
+        } else if self.header.depth == ColorDepth::Indexed {
+            let transparent_index = if background {
+                None
+            } else {
+                Some(self.header.transparent_index)
+            };
+            Ok(Image::new_from_indexed(
+                w,
+                h,
+                &data,
+                palette,
+                transparent_index,
+            ))
+        } else {
+            Ok(Image::new_from_data(w, h, data))
+        }
+    }
+
+    // Splits the image data of a tileset, which has every tile stacked
+    // vertically, into one image per tile.
+    fn decode_tiles(
+        &self,
+        tileset: &Tileset,
+        data: Vec<u8>,
+        palette: &Palette,
+    ) -> Result<Vec<Image>, AsepriteError> {
+        let tile_size =
+            tileset.tile_width as usize * tileset.tile_height as usize * self.bytes_per_pixel()?;
+        if tile_size == 0 || data.len() != tile_size * tileset.tile_count as usize {
+            return Err(AsepriteError::CorruptFile(format!(
+                "tileset {} of {} tiles has {} bytes of pixel data",
+                tileset.id,
+                tileset.tile_count,
+                data.len()
+            )));
+        }
+        data.chunks_exact(tile_size)
+            .map(|tile| {
+                self.decode_image(
+                    tileset.tile_width,
+                    tileset.tile_height,
+                    tile.to_vec(),
+                    palette,
+                    false,
+                )
+            })
+            .collect()
+    }
+
+    // Renders the tiles of a tilemap cel into an image. Tiles that can't be
+    // found are left empty.
+    fn render_tile_grid(&self, grid: &TileGrid) -> Result<Image, AsepriteError> {
+        let tileset = self.tileset_by_id(grid.tileset_id).ok_or_else(|| {
+            AsepriteError::CorruptFile(format!("missing tileset {}", grid.tileset_id))
+        })?;
+
+        let tw = tileset.tile_width;
+        let th = tileset.tile_height;
+        // Tiles are drawn at i16 positions, so the whole tilemap has to fit in
+        // that range.
+        let w = u32::from(grid.width) * u32::from(tw);
+        let h = u32::from(grid.height) * u32::from(th);
+        let max = i16::MAX as u32;
+        if w > max || h > max {
+            return Err(AsepriteError::Unimplemented(format!(
+                "tilemap of {}x{} tiles of {}x{} is {}x{} pixels, more than the {} per side that can be drawn",
+                grid.width, grid.height, tw, th, w, h, max
+            )));
+        }
+        let mut image = Image::new(w as u16, h as u16);
+        // Every tile is inside the image, so these multiplications can't
+        // overflow either.
+        for ty in 0..grid.height {
+            for tx in 0..grid.width {
+                let tile = grid.tile(tx, ty).expect("in bounds");
+                if let Some(t) = tileset.tile(tile.id) {
+                    let t = t.flipped(tile.x_flip, tile.y_flip, tile.diagonal_flip);
+                    image.draw(
+                        (tx * tw).try_into()?,
+                        (ty * th).try_into()?,
+                        &t,
+                        255,
+                        BlendMode::Normal,
+                    )?;
+                }
+            }
+        }
+        Ok(image)
+    }
 }
 
 #[derive(Debug)]
@@ -368,6 +1294,87 @@ where
     }
 }
 
+#[cfg(feature = "tiled")]
+#[test]
+fn test_tiled() {
+    use std::fs::File;
+
+    datadriven::walk("tiled_tests", |f| {
+        let mut current_file = None;
+        f.run(move |test_case| -> String {
+            match test_case.directive.as_str() {
+                "load" => {
+                    let f = File::open(test_case.input.trim()).unwrap();
+                    current_file = Some(AsepriteFile::load(f).unwrap());
+                    "ok\n".into()
+                }
+                "tiled" => {
+                    let export = current_file.as_ref().unwrap().to_tiled(0, "map").unwrap();
+                    let mut out = export.map;
+                    for tileset in export.tilesets {
+                        out.push_str(&tileset.tsx);
+                    }
+                    out
+                }
+                _ => panic!("unhandled {}", test_case.directive),
+            }
+        })
+    });
+}
+
+#[cfg(feature = "tiled")]
+#[test]
+fn test_tiled_images() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let decode = |bytes: &[u8]| {
+        let decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        buf.truncate(info.buffer_size());
+        (info.width, info.height, buf)
+    };
+
+    // Tileset images have every tile stacked vertically.
+    let ase = AsepriteFile::load(File::open("testdata/tile_sizes.ase")?)?;
+    let export = ase.to_tiled(0, "map")?;
+    assert_eq!(export.tilesets.len(), 2);
+    for (tileset, exported) in ase.tilesets().iter().zip(&export.tilesets) {
+        let (w, h, data) = decode(&exported.image);
+        assert_eq!(
+            (w, h),
+            (
+                u32::from(tileset.tile_width),
+                u32::from(tileset.tile_height) * tileset.tile_count
+            )
+        );
+        let tiles: Vec<u8> = tileset.tiles.iter().flat_map(|t| t.data.clone()).collect();
+        assert_eq!(data, tiles);
+    }
+
+    // Image layers are exported as they're rendered.
+    let ase = AsepriteFile::load(File::open("testdata/groups.ase")?)?;
+    let export = ase.to_tiled(0, "map")?;
+    assert_eq!(export.images.len(), 4);
+    for (name, image) in &export.images {
+        let layer: usize = name["map_layer".len()..name.len() - ".png".len()].parse()?;
+        let expected = ase.layer_image(0, layer)?;
+        let (w, h, data) = decode(image);
+        assert_eq!((w, h), (expected.width.into(), expected.height.into()));
+        assert_eq!(data, expected.data);
+    }
+
+    // Opacity follows the header flags, as when rendering.
+    assert!(export.map.contains(r#"name="body" opacity="0.502""#));
+    let mut bytes = std::fs::read("testdata/groups.ase")?;
+    bytes[14] = constants::ASE_FILE_FLAG_LAYER_WITH_OPACITY as u8;
+    let old = AsepriteFile::load(std::io::Cursor::new(bytes))?;
+    assert!(!old.to_tiled(0, "map")?.map.contains("opacity="));
+
+    Ok(())
+}
+
 #[test]
 fn test_metadata() {
     use std::fs::File;
@@ -377,21 +1384,75 @@ fn test_metadata() {
         f.run(move |test_case| -> String {
             match test_case.directive.as_str() {
                 "load" => {
-                    let f = File::open(&test_case.input.trim()).unwrap();
+                    let f = File::open(test_case.input.trim()).unwrap();
                     current_file = Some(AsepriteFile::load(f).unwrap());
                     "ok\n".into()
                 }
                 "header" => {
-                    format!("{:#?}\n", current_file.as_ref().unwrap().header)
+                    format!("{:#?}\n", current_file.as_ref().unwrap().header())
+                }
+                "layer_tree" => {
+                    let file = current_file.as_ref().unwrap();
+                    let mut out = String::new();
+                    let mut stack: Vec<_> = file.layer_children(None).collect();
+                    stack.reverse();
+                    while let Some(i) = stack.pop() {
+                        out.push_str(&format!("{}\n", file.layer_path(i).unwrap()));
+                        let mut children: Vec<_> = file.layer_children(Some(i)).collect();
+                        children.reverse();
+                        stack.extend(children);
+                    }
+                    out
+                }
+                "masks" => {
+                    let file = current_file.as_ref().unwrap();
+                    let mut out = String::new();
+                    for mask in file.masks() {
+                        out.push_str(&format!("{} at ({}, {}):\n", mask.name, mask.x, mask.y));
+                        for y in 0..mask.height {
+                            for x in 0..mask.width {
+                                out.push(if mask.get(x, y) { '#' } else { '.' });
+                            }
+                            out.push('\n');
+                        }
+                    }
+                    out
+                }
+                "palette" => {
+                    format!("{:#?}\n", current_file.as_ref().unwrap().palette())
                 }
                 "frames" => {
-                    format!("{:#?}\n", current_file.as_ref().unwrap().frames)
+                    let file = current_file.as_ref().unwrap();
+                    let mut out = String::new();
+                    for (i, frame) in file.frames().iter().enumerate() {
+                        out.push_str(&format!("frame {}: {}ms\n", i, frame.duration));
+                        for cel in frame.cels() {
+                            let image = file.cel_image(i, cel.layer_index).unwrap();
+                            let (w, h) = image.map_or((0, 0), |i| (i.width, i.height));
+                            out.push_str(&format!(
+                                "  layer {} at ({}, {}), opacity {}, z-index {}: {}x{}\n",
+                                cel.layer_index, cel.x, cel.y, cel.opacity, cel.z_index, w, h
+                            ));
+                        }
+                    }
+                    out
                 }
                 "tags" => {
-                    format!("{:#?}\n", current_file.as_ref().unwrap().tags)
+                    format!("{:#?}\n", current_file.as_ref().unwrap().tags())
+                }
+                "tag" => {
+                    let name = test_case.input.trim();
+                    format!("{:#?}\n", current_file.as_ref().unwrap().tag_by_name(name))
                 }
                 "slices" => {
-                    format!("{:#?}\n", current_file.as_ref().unwrap().slices)
+                    format!("{:#?}\n", current_file.as_ref().unwrap().slices())
+                }
+                "slice" => {
+                    let name = test_case.input.trim();
+                    format!(
+                        "{:#?}\n",
+                        current_file.as_ref().unwrap().slice_by_name(name)
+                    )
                 }
                 _ => panic!("unhandled {}", test_case.directive),
             }
@@ -417,6 +1478,18 @@ fn test_files() -> Result<(), AsepriteError> {
         "linked.ase",
         "linked2.ase",
         "frames.ase",
+        "indexed.ase",
+        "grayscale.ase",
+        "groups.ase",
+        "tilemap.ase",
+        "raw.ase",
+        "raw_indexed.ase",
+        "raw_grayscale.ase",
+        "precise_bounds.ase",
+        "old_palette.ase",
+        "z_index.ase",
+        "palette_change.ase",
+        "blend_modes.ase",
     ] {
         let mut path = PathBuf::new();
         path.push("testdata");
@@ -427,7 +1500,7 @@ fn test_files() -> Result<(), AsepriteError> {
         let f = File::open(input_file)?;
         let ase = AsepriteFile::load(f)?;
 
-        for (idx, frame) in ase.frames.iter().enumerate() {
+        for idx in 0..ase.frames().len() {
             // Load the expected.
             let mut expected = path.clone();
             expected.push(format!("{}.{}.png", fname, idx));
@@ -437,9 +1510,706 @@ fn test_files() -> Result<(), AsepriteError> {
             let info = reader.next_frame(&mut buf).unwrap();
             let bytes = &buf[..info.buffer_size()];
 
-            assert_eq!(bytes, frame.image.data);
+            assert_eq!(bytes, ase.frame_image(idx)?.data);
         }
     }
 
     Ok(())
 }
+
+#[test]
+fn test_blend_modes() {
+    let b = Color::from_rgba(255, 128, 0, 255);
+    let s = Color::from_rgba(128, 128, 128, 255);
+    for (mode, expected) in [
+        (BlendMode::Normal, (128, 128, 128)),
+        (BlendMode::Multiply, (128, 64, 0)),
+        (BlendMode::Screen, (255, 192, 128)),
+        (BlendMode::Overlay, (255, 128, 0)),
+        (BlendMode::Darken, (128, 128, 0)),
+        (BlendMode::Lighten, (255, 128, 128)),
+        (BlendMode::Difference, (127, 0, 128)),
+        (BlendMode::Addition, (255, 255, 128)),
+        (BlendMode::Subtract, (127, 0, 0)),
+        (BlendMode::Divide, (255, 255, 0)),
+        (BlendMode::Unknown(99), (128, 128, 128)),
+    ] {
+        let r = b.blend(s, 255, mode);
+        assert_eq!(
+            (r.r(), r.g(), r.b(), r.a()),
+            (expected.0, expected.1, expected.2, 255)
+        );
+    }
+
+    // Colors away from the ends of the range, for the modes that would
+    // saturate above. The expected values follow Aseprite's blend_funcs.cpp.
+    let b = Color::from_rgba(40, 160, 220, 255);
+    let s = Color::from_rgba(200, 60, 100, 255);
+    for (mode, expected) in [
+        (BlendMode::ColorDodge, (185, 209, 255)),
+        (BlendMode::ColorBurn, (0, 0, 166)),
+        (BlendMode::HardLight, (162, 75, 173)),
+        (BlendMode::SoftLight, (74, 128, 213)),
+        (BlendMode::Exclusion, (178, 144, 148)),
+        (BlendMode::Hue, (250, 70, 122)),
+        (BlendMode::Saturation, (60, 153, 200)),
+        (BlendMode::Color, (224, 84, 124)),
+        (BlendMode::Luminosity, (15, 135, 195)),
+    ] {
+        let r = b.blend(s, 255, mode);
+        assert_eq!(
+            (r.r(), r.g(), r.b(), r.a()),
+            (expected.0, expected.1, expected.2, 255),
+            "{:?}",
+            mode
+        );
+    }
+
+    // Over a transparent backdrop, the source is left as-is.
+    let r = Color::from_rgba(0, 0, 0, 0).blend(s, 255, BlendMode::Multiply);
+    assert_eq!((r.r(), r.g(), r.b(), r.a()), (200, 60, 100, 255));
+}
+
+#[test]
+fn test_layer_paths() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/groups.ase")?)?;
+    assert_eq!(ase.layer_index_by_path("body/inner/hand"), Some(6));
+    assert_eq!(ase.layer_index_by_path("body/hand"), None);
+    assert_eq!(ase.layer_parent(6), Some(5));
+    assert_eq!(ase.layer_parent(3), None);
+    assert_eq!(ase.layer_children(Some(3)).collect::<Vec<_>>(), vec![4, 5]);
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_metadata() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/groups.ase")?)?;
+    assert_eq!(ase.header().depth, ColorDepth::Rgba);
+    assert!(ase.header().flags.layer_opacity_valid());
+    let layer = &ase.layers()[3];
+    assert_eq!(layer.layer_type, LayerType::Group);
+    assert!(layer.flags.visible());
+    assert!(!layer.flags.reference());
+
+    assert_eq!(LayerType::from(7), LayerType::Unknown(7));
+    assert_eq!(
+        AnimationDirection::from(3),
+        AnimationDirection::PingPongReverse
+    );
+    assert_eq!(AnimationDirection::from(9), AnimationDirection::Unknown(9));
+    assert_eq!(ColorDepth::from(24), ColorDepth::Unknown(24));
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_frames() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/tag_directions.ase")?)?;
+    let frames = |name| {
+        let tag = ase.tag_by_name(name).unwrap();
+        tag.frame_sequence()
+    };
+    assert_eq!(frames("fwd"), vec![0, 1, 2, 0, 1, 2]);
+    assert_eq!(frames("rev"), vec![3, 2, 1]);
+    assert_eq!(frames("pp"), vec![0, 1, 2, 3, 2, 1, 0, 1, 2, 3]);
+    assert_eq!(frames("ppr"), vec![2, 1, 0, 1]);
+
+    let tag = ase.tag_by_name("rev").unwrap();
+    assert_eq!(tag.repeat, 0);
+    assert_eq!(ase.tag_frames(tag), vec![(3, 40), (2, 30), (1, 20)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_animation_player() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/animation.ase")?)?;
+    assert!(AnimationPlayer::new(&ase, "run").is_none());
+
+    let mut walk = AnimationPlayer::new(&ase, "walk").unwrap();
+    assert!(walk.is_looping());
+    let events = walk.advance(0.0);
+    assert!(matches!(events[..], [AnimationEvent::Tag(t)] if t.user_data.string == "walking"));
+    assert_eq!(walk.current_frame(), 0);
+
+    let events = walk.advance(120.0);
+    assert_eq!(walk.current_frame(), 1);
+    assert!(matches!(
+        events[..],
+        [AnimationEvent::Cel { frame: 1, cel }] if cel.user_data.string == "footstep"
+    ));
+
+    // 20ms into frame 1, so this goes through frame 2 and back to frame 0.
+    walk.set_speed(2.0);
+    let events = walk.advance(70.0);
+    assert_eq!(walk.current_frame(), 0);
+    assert_eq!(events.len(), 1);
+    assert!(!walk.is_finished());
+
+    // Going around a million times takes no longer than going around once.
+    walk.set_speed(1.0);
+    assert!(walk.advance(f64::INFINITY).is_empty());
+    assert!(walk.advance(f64::NAN).is_empty());
+    assert_eq!(walk.advance(250.0 * 1_000_000.0).len(), 2);
+    assert_eq!(walk.current_frame(), 0);
+
+    // Frames 0, 1, 2, 1, 0, then it stops.
+    let mut jump = AnimationPlayer::new(&ase, "jump").unwrap();
+    assert!(!jump.is_looping());
+    assert!(jump.advance(0.0).is_empty());
+    jump.advance(f64::INFINITY);
+    assert!(!jump.is_finished());
+    jump.advance(280.0);
+    assert_eq!(jump.current_frame(), 1);
+    jump.advance(1000.0);
+    assert!(jump.is_finished());
+    assert_eq!(jump.current_frame(), 0);
+
+    jump.reset();
+    assert!(!jump.is_finished());
+    assert_eq!(jump.current_frame(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_timeline() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/animation.ase")?)?;
+    assert_eq!(ase.total_duration(), 250);
+    assert_eq!(ase.frame_start_time(2), Some(150));
+    assert_eq!(ase.frame_start_time(3), None);
+    assert_eq!(ase.frame_at_time(0), Some(0));
+    assert_eq!(ase.frame_at_time(149), Some(1));
+    assert_eq!(ase.frame_at_time(150), Some(2));
+    assert_eq!(ase.frame_at_time(250), None);
+
+    // Frames 0, 1, 2, 1, 0.
+    let jump = ase.tag_by_name("jump").unwrap();
+    assert_eq!(ase.tag_duration(jump), 400);
+    assert_eq!(ase.tag_frame_at_time(jump, 260), Some(1));
+    assert_eq!(ase.tag_frame_at_time(jump, 300), Some(0));
+    assert_eq!(ase.tag_frame_start_time(jump, 2), Some(150));
+
+    let ase = AsepriteFile::load(File::open("testdata/zero_duration.ase")?)?;
+    assert_eq!(ase.frame_duration(0), Some(80));
+    assert_eq!(ase.total_duration(), 110);
+    assert_eq!(ase.frame_at_time(90), Some(1));
+
+    Ok(())
+}
+
+#[test]
+fn test_cels() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/linked.ase")?)?;
+    let cel = ase.cel(1, 0).unwrap();
+    assert!(cel.is_linked());
+    let linked = cel.linked_frame().unwrap();
+    assert!(!ase.cel(linked, 0).unwrap().is_linked());
+    assert_eq!(
+        ase.cel_image(1, 0)?.unwrap().data,
+        ase.cel_image(linked, 0)?.unwrap().data
+    );
+
+    let ase = AsepriteFile::load(File::open("testdata/z_index.ase")?)?;
+    let cel = ase.cel(0, 2).unwrap();
+    assert_eq!((cel.x, cel.y, cel.opacity, cel.z_index), (1, 0, 255, 0));
+    let image = ase.cel_image(0, 2)?.unwrap();
+    assert_eq!((image.width, image.height), (1, 1));
+    assert_eq!(image.data, vec![0, 0, 255, 255]);
+    assert!(ase.cel(0, 3).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_lazy_loading() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    // The pixels of this file are garbage, but nothing looks at them until
+    // the frame is rendered.
+    let ase = AsepriteFile::load(File::open("testdata/corrupt_cel.ase")?)?;
+    assert_eq!(ase.slice_by_name("hitbox").unwrap().keys.len(), 1);
+    assert!(ase.cel(0, 0).is_some());
+    assert!(ase.frame_image(0).is_err());
+
+    // Asking for a frame that doesn't exist is an error, not a panic.
+    let ase = AsepriteFile::load(File::open("testdata/linked.ase")?)?;
+    assert!(ase.frame_image(2).is_err());
+    assert!(ase.render_srgb(2).is_err());
+    assert!(ase.layer_image(2, 0).is_err());
+    assert!(ase.render_frame(2, &RenderOptions::new()).is_err());
+
+    let mut ase = AsepriteFile::load(File::open("testdata/linked.ase")?)?;
+    let uncached = ase.frame_image(1)?.into_owned();
+    ase.set_cache(true);
+    assert_eq!(ase.frame_image(1)?.data, uncached.data);
+    assert!(matches!(ase.frame_image(1)?, Cow::Borrowed(_)));
+    assert!(ase.frame(1).unwrap().image.get().is_some());
+    assert!(ase.frame(0).unwrap().cel(0).unwrap().image.get().is_some());
+    ase.set_cache(false);
+    assert!(ase.frame(1).unwrap().image.get().is_none());
+
+    // Frame 1 links to the cel of frame 0, but changes the palette.
+    let mut ase = AsepriteFile::load(File::open("testdata/palette_change.ase")?)?;
+    ase.set_cache(true);
+    for frame in 0..3 {
+        let uncached = ase.frame_image(frame)?;
+        assert_eq!(ase.frame_image(frame)?.data, uncached.data);
+    }
+    assert_eq!(ase.frame_image(0)?.data[..4], [255, 0, 0, 255]);
+    assert_eq!(ase.frame_image(1)?.data[..4], [0, 255, 0, 255]);
+    assert_eq!(ase.frame_palette(1).unwrap().get(1).unwrap().g, 255);
+    assert_eq!(ase.palette().get(1).unwrap().b, 255);
+
+    Ok(())
+}
+
+#[test]
+fn test_render_frame() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/groups.ase")?)?;
+    assert_eq!(
+        ase.render_frame(0, &RenderOptions::new())?.data,
+        ase.frame_image(0)?.data
+    );
+
+    // Selecting or excluding every top-level layer is the same as doing
+    // nothing, and the same as doing everything.
+    let all = ase.render_frame(0, &RenderOptions::new())?;
+    let mut only = RenderOptions::new();
+    let mut none = RenderOptions::new();
+    for i in ase.layer_children(None) {
+        only = only.only(LayerFilter::Index(i));
+        none = none.exclude(LayerFilter::Index(i));
+    }
+    assert_eq!(ase.render_frame(0, &only)?.data, all.data);
+    assert!(ase.render_frame(0, &none)?.data.iter().all(|&b| b == 0));
+
+    let ase = AsepriteFile::load(File::open("testdata/invisible_layer.ase")?)?;
+    let hidden = |_, l: &LayerHeader| !l.visible();
+    let shown = ase.render_frame(
+        0,
+        &RenderOptions::new().show(LayerFilter::Predicate(&hidden)),
+    )?;
+    assert_ne!(shown.data, ase.frame_image(0)?.data);
+
+    let ase = AsepriteFile::load(File::open("testdata/z_index.ase")?)?;
+    let pixels = |options: &RenderOptions| {
+        ase.render_frame(0, options)
+            .unwrap()
+            .data
+            .chunks(4)
+            .map(|p| p.to_vec())
+            .collect::<Vec<_>>()
+    };
+    let (red, green, blue, clear) = (
+        vec![255, 0, 0, 255],
+        vec![0, 255, 0, 255],
+        vec![0, 0, 255, 255],
+        vec![0, 0, 0, 0],
+    );
+    assert_eq!(
+        pixels(&RenderOptions::new().only(LayerFilter::Name("middle"))),
+        vec![green.clone(), green.clone()]
+    );
+    assert_eq!(
+        pixels(&RenderOptions::new().exclude(LayerFilter::Path("bottom"))),
+        vec![green.clone(), blue.clone()]
+    );
+    assert_eq!(
+        pixels(
+            &RenderOptions::new()
+                .only(LayerFilter::Predicate(&|i, _| i == 2))
+                .hide(LayerFilter::Index(2))
+        ),
+        vec![clear.clone(), clear]
+    );
+    let no_top = pixels(&RenderOptions::new().opacity(LayerFilter::Name("top"), 0));
+    assert_eq!(no_top, vec![red.clone(), red]);
+
+    Ok(())
+}
+
+#[test]
+fn test_opacity_flags() -> Result<(), AsepriteError> {
+    // A Multiply layer at 50% in a group at 50%, over an opaque backdrop.
+    let bytes = std::fs::read("testdata/group_blend.ase")?;
+    for (flags, expected) in [
+        // Everything is opaque, and the child multiplies with the backdrop.
+        (0, [78, 78, 49, 255]),
+        // Only the child's own opacity applies.
+        (1, [139, 89, 50, 255]),
+        // The group is isolated, so the child has nothing to multiply with.
+        (2, [150, 150, 150, 255]),
+        (3, [175, 125, 100, 255]),
+    ] {
+        let mut bytes = bytes.clone();
+        bytes[14] = flags;
+        let ase = AsepriteFile::load(std::io::Cursor::new(bytes))?;
+        assert_eq!(ase.frame_image(0)?.data, expected, "flags {}", flags);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_layer_uuids() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/z_index.ase")?)?;
+    let uuid: [u8; 16] = std::array::from_fn(|i| 16 + i as u8);
+    assert_eq!(ase.layer_by_uuid(&uuid).unwrap().name, "middle");
+    assert_eq!(ase.frame(0).unwrap().cel(0).unwrap().z_index, 1);
+
+    let ase = AsepriteFile::load(File::open("testdata/groups.ase")?)?;
+    assert!(ase.layers().iter().all(|l| l.uuid.is_none()));
+
+    Ok(())
+}
+
+#[test]
+fn test_rect() {
+    let r = Rect {
+        x: -4,
+        y: -2,
+        w: 8,
+        h: 4,
+    };
+    assert!(r.contains(-4, -2));
+    assert!(r.contains(3, 1));
+    assert!(!r.contains(4, 0));
+    assert!(!r.contains(0, -3));
+
+    let other = r.translate(6, 3).unwrap();
+    assert_eq!(
+        other,
+        Rect {
+            x: 2,
+            y: 1,
+            w: 8,
+            h: 4,
+        }
+    );
+    assert_eq!(
+        r.intersect(&other),
+        Some(Rect {
+            x: 2,
+            y: 1,
+            w: 2,
+            h: 1,
+        })
+    );
+    assert_eq!(r.intersect(&r.translate(8, 0).unwrap()), None);
+    assert_eq!(r.translate(0, i32::MIN), None);
+}
+
+#[test]
+fn test_slice_keys() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/slice_keys.ase")?)?;
+    let hitbox = ase.slice_by_name("hitbox").unwrap();
+    assert!(hitbox.key_at(0).is_none());
+    assert_eq!(hitbox.key_at(2).unwrap().frame, 1);
+    assert_eq!(hitbox.key_at(3).unwrap().bounds.x, 1);
+    assert_eq!(hitbox.key_at(100).unwrap().frame, 3);
+
+    let names = |frame| {
+        ase.slices_at(frame)
+            .map(|(s, _)| s.name.as_str())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names(0), vec!["always"]);
+    assert_eq!(names(1), vec!["hitbox", "always"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_tile_grid() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/tilemap.ase")?)?;
+    let grid = ase.tile_grid(0, 0).unwrap();
+    assert_eq!((grid.width, grid.height), (2, 1));
+    assert_eq!(
+        grid.tiles,
+        vec![
+            Tile {
+                id: 1,
+                x_flip: true,
+                y_flip: false,
+                diagonal_flip: false,
+            },
+            Tile {
+                id: 1,
+                x_flip: false,
+                y_flip: false,
+                diagonal_flip: true,
+            },
+        ]
+    );
+    let tileset = ase.tileset_by_id(grid.tileset_id).unwrap();
+    assert_eq!(tileset.tile(2).unwrap().data, [0, 0, 255, 255].repeat(4));
+
+    // Grids too wide to draw are an error rather than an overflow or a
+    // panic, even when their size fits in a u16.
+    for width in [u16::MAX, i16::MAX as u16 / tileset.tile_width + 1] {
+        let wide = TileGrid {
+            width,
+            ..grid.clone()
+        };
+        assert!(matches!(
+            ase.render_tile_grid(&wide),
+            Err(AsepriteError::Unimplemented(_))
+        ));
+    }
+    let mut image = Image::new(1, 1);
+    let huge = Image::new(40000, 1);
+    assert!(image.draw(0, 0, &huge, 255, BlendMode::Normal).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_cel_extra() -> Result<(), AsepriteError> {
+    let bytes = std::fs::read("testdata/precise_bounds.ase")?;
+    // The flags DWORD of the cel extra chunk, after the header, the frame
+    // header, and the layer and cel chunks.
+    let flags_at = 128 + 16 + 33 + 41 + 6;
+    assert_eq!(bytes[flags_at - 2..flags_at], [0x06, 0x20]);
+
+    for (flags, precise) in [
+        (0x0000_0001, true),
+        (0x0001_0001, true),
+        (0xffff_0000, false),
+    ] {
+        let mut bytes = bytes.clone();
+        bytes[flags_at..flags_at + 4].copy_from_slice(&u32::to_le_bytes(flags));
+        let ase = AsepriteFile::load(std::io::Cursor::new(bytes))?;
+        let cel = ase.cel(0, 0).unwrap();
+        let extra = cel.extra.as_ref().unwrap();
+        assert_eq!(extra.flags, flags);
+        assert_eq!((extra.width, extra.height), (4.0, 2.0));
+        assert_eq!(
+            cel.precise_bounds().is_some(),
+            precise,
+            "flags {:#x}",
+            flags
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_old_palette() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    // The 0x0011 chunk has 6-bit colors, which are scaled up to 8 bits.
+    let ase = AsepriteFile::load(File::open("testdata/old_palette.ase")?)?;
+    let rgb = |ase: &AsepriteFile, i| {
+        let e = ase.palette().get(i).unwrap();
+        (e.r, e.g, e.b)
+    };
+    assert_eq!(rgb(&ase, 0), (255, 0, 0));
+    assert_eq!(rgb(&ase, 1), (0, 130, 0));
+    assert_eq!(rgb(&ase, 3), (0, 0, 255));
+
+    // The same colors in a 0x0004 chunk are already 8-bit.
+    let mut bytes = std::fs::read("testdata/old_palette.ase")?;
+    bytes[148] = constants::ASE_FILE_CHUNK_FLI_COLOR2 as u8;
+    let ase = AsepriteFile::load(std::io::Cursor::new(bytes))?;
+    assert_eq!(rgb(&ase, 0), (63, 0, 0));
+    assert_eq!(rgb(&ase, 1), (0, 32, 0));
+
+    Ok(())
+}
+
+#[test]
+fn test_user_data() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/user_data.ase")?)?;
+    assert_eq!(ase.user_data().string, "sprite");
+    assert_eq!(ase.layers()[0].user_data().string, "layer");
+    assert_eq!(
+        ase.frame(0).unwrap().cel(0).unwrap().user_data().string,
+        "cel"
+    );
+    assert_eq!(ase.tags()[0].user_data().string, "first tag");
+    assert_eq!(ase.tags()[1].user_data().string, "second tag");
+    assert_eq!(ase.slices()[0].user_data().string, "slice");
+    let tileset = &ase.tilesets()[0];
+    assert_eq!(tileset.user_data().string, "tileset");
+    assert_eq!(tileset.tile_user_data(1).unwrap().string, "tile");
+    assert_eq!(
+        (
+            tileset.user_data.r,
+            tileset.user_data.g,
+            tileset.user_data.b
+        ),
+        (1, 2, 3)
+    );
+
+    // This frame starts with user data, which has nothing to attach to.
+    let ase = AsepriteFile::load(File::open("testdata/user_data_frames.ase")?)?;
+    assert_eq!(ase.frame(0).unwrap().cel(1).unwrap().user_data().string, "");
+    assert_eq!(
+        ase.frame(1).unwrap().cel(0).unwrap().user_data().string,
+        "cel"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_properties() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/properties.ase")?)?;
+    let user_data = ase.slice_by_name("hitbox").unwrap().user_data();
+    assert_eq!(user_data.string, "hit");
+    let props = user_data.properties();
+    assert_eq!(props.get_int("damage"), Some(5));
+    assert_eq!(props.get_bool("active"), Some(true));
+    assert_eq!(props.get_float("knockback"), Some(1.5));
+    assert_eq!(props.get_str("event"), Some("hurt"));
+    assert_eq!(
+        props.get("origin"),
+        Some(&PropertyValue::Point { x: -2, y: 3 })
+    );
+    assert_eq!(
+        props.get_vector("tags"),
+        Some(
+            &[
+                PropertyValue::UInt8(1),
+                PropertyValue::String("fire".into())
+            ][..]
+        )
+    );
+    assert_eq!(
+        props.get_map("sound").and_then(|m| m.get_float("volume")),
+        Some(0.25)
+    );
+    assert_eq!(
+        user_data
+            .extension_properties(7)
+            .and_then(|p| p.get_int("x")),
+        Some(-1)
+    );
+
+    // Extension 7 has a property of an unknown type, so it and extension 8
+    // are skipped, but everything else loads.
+    let ase = AsepriteFile::load(File::open("testdata/properties_unknown.ase")?)?;
+    let user_data = ase.slice_by_name("hitbox").unwrap().user_data();
+    assert_eq!(user_data.properties().get_int("damage"), Some(5));
+    assert!(user_data.extension_properties(7).is_none());
+    assert!(user_data.extension_properties(8).is_none());
+    assert_eq!(
+        ase.slice_by_name("after").unwrap().user_data().string,
+        "after"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_color_profile() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    for (fname, gamma) in [("linear.ase", Some(1.0)), ("icc.ase", None)] {
+        let ase = AsepriteFile::load(File::open(format!("testdata/{}", fname))?)?;
+        let profile = ase.color_profile().unwrap();
+        assert_eq!(profile.fixed_gamma(), gamma);
+        assert_eq!(ase.frame_image(0)?.data, [128, 128, 128, 200]);
+        assert_eq!(ase.render_srgb(0)?.data, [188, 188, 188, 200]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_external_tileset() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let f = File::open("testdata/external_tileset.ase")?;
+    let ase = AsepriteFile::load(f)?;
+    let file = ase.external_file(1).unwrap();
+    assert_eq!(file.name, "tilemap.ase");
+    assert!(file.is_tileset());
+    assert!(ase.tilesets()[0].tiles.is_empty());
+
+    let f = File::open("testdata/external_tileset.ase")?;
+    let ase = AsepriteFile::load_with_resolver(f, DirectoryResolver::new("testdata"))?;
+    let expected = AsepriteFile::load(File::open("testdata/tilemap.ase")?)?;
+    assert_eq!(ase.frame_image(0)?.data, expected.frame_image(0)?.data);
+
+    Ok(())
+}
+
+#[test]
+fn test_external_palette() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let f = File::open("testdata/external_palette.ase")?;
+    let ase = AsepriteFile::load(f)?;
+    assert!(ase.external_file(1).unwrap().is_palette());
+    assert!(ase.external_palette(1).is_none());
+
+    let f = File::open("testdata/external_palette.ase")?;
+    let ase = AsepriteFile::load_with_resolver(f, DirectoryResolver::new("testdata"))?;
+    let expected = AsepriteFile::load(File::open("testdata/indexed.ase")?)?;
+    assert_eq!(ase.external_palette(1), Some(expected.palette()));
+    assert_ne!(ase.palette(), expected.palette());
+
+    // Other palette formats can be read by overriding resolve_palette.
+    struct Gray;
+    impl ExternalFileResolver for Gray {
+        fn resolve(&mut self, _: &ExternalFile) -> Result<AsepriteFile, AsepriteError> {
+            unreachable!()
+        }
+
+        fn resolve_palette(&mut self, _: &ExternalFile) -> Result<Palette, AsepriteError> {
+            Ok(Palette {
+                entries: vec![PaletteEntry {
+                    r: 128,
+                    g: 128,
+                    b: 128,
+                    a: 255,
+                    name: None,
+                }],
+            })
+        }
+    }
+    let f = File::open("testdata/external_palette.ase")?;
+    let ase = AsepriteFile::load_with_resolver(f, Gray)?;
+    assert_eq!(ase.external_palette(1).unwrap().get(0).unwrap().r, 128);
+
+    Ok(())
+}
+
+#[test]
+fn test_grayscale_data() -> Result<(), AsepriteError> {
+    use std::fs::File;
+
+    let ase = AsepriteFile::load(File::open("testdata/grayscale.ase")?)?;
+    let image = ase.frame_image(0)?;
+    assert_eq!(image.to_grayscale(), vec![75, 255, 200, 128]);
+
+    Ok(())
+}
@@ -1,11 +1,67 @@
-use std::io::{Read, Seek};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek},
+};
 
 use crate::{
     constants,
     parser::{Parse, Parser, Skip},
-    AsepriteError,
+    AsepriteError, BlendMode, Image, Properties,
 };
 
+/// The format the pixels of a sprite are stored in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorDepth {
+    Rgba,
+    Grayscale,
+    Indexed,
+    /// A color depth this crate doesn't know about, in bits per pixel.
+    Unknown(u16),
+}
+
+impl From<u16> for ColorDepth {
+    fn from(v: u16) -> Self {
+        match v {
+            32 => ColorDepth::Rgba,
+            16 => ColorDepth::Grayscale,
+            8 => ColorDepth::Indexed,
+            v => ColorDepth::Unknown(v),
+        }
+    }
+}
+
+impl Parse for ColorDepth {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        Ok(u16::parse(p)?.into())
+    }
+}
+
+/// The flags in the [FileHeader].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FileFlags(pub u32);
+
+impl FileFlags {
+    /// Whether the opacity of layers is meaningful. Older files always have
+    /// fully opaque layers.
+    pub fn layer_opacity_valid(&self) -> bool {
+        self.0 & constants::ASE_FILE_FLAG_LAYER_WITH_OPACITY != 0
+    }
+
+    pub fn group_opacity_valid(&self) -> bool {
+        self.0 & constants::ASE_FILE_FLAG_GROUP_WITH_OPACITY != 0
+    }
+
+    pub fn layers_have_uuid(&self) -> bool {
+        self.0 & constants::ASE_FILE_FLAG_LAYERS_HAVE_UUID != 0
+    }
+}
+
+impl Parse for FileFlags {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        Ok(FileFlags(p.next()?))
+    }
+}
+
 /// The header for the entire Aseprite file.
 #[derive(Debug)]
 pub struct FileHeader {
@@ -14,12 +70,12 @@ pub struct FileHeader {
     pub frames: u16,
     pub width: u16,
     pub height: u16,
-    pub depth: u16,
-    pub flags: u32,
+    pub depth: ColorDepth,
+    pub flags: FileFlags,
     pub speed: u16,
     pub next: u32,
     pub frit: u32,
-    pub transparent_index: u32,
+    pub transparent_index: u8,
     _skip: Skip<3>,
     pub ncolors: u16,
     pub pixel_width: u8,
@@ -61,26 +117,116 @@ impl Parse for FileHeader {
 
 #[derive(Debug)]
 pub struct LayerHeader {
-    pub flags: u16,
-    pub layer_type: u16,
+    pub flags: LayerFlags,
+    pub layer_type: LayerType,
     pub child_level: u16,
     pub default_width: u16,
     pub default_height: u16,
-    pub blend_mode: u16,
+    pub blend_mode: BlendMode,
     pub opacity: u8,
     _skip: Skip<3>,
     pub name: String,
+    /// The ID of the [Tileset] used by a tilemap layer.
+    pub tileset_index: Option<u32>,
+    /// Only set if the file header says layers have UUIDs.
+    pub uuid: Option<[u8; 16]>,
+    pub user_data: UserData,
 }
 
 impl LayerHeader {
-    pub(crate) fn visible(&self) -> bool {
-        self.flags & constants::LAYER_VISIBLE != 0
+    pub fn user_data(&self) -> &UserData {
+        &self.user_data
+    }
+
+    pub fn visible(&self) -> bool {
+        self.flags.visible()
+    }
+
+    pub fn background(&self) -> bool {
+        self.flags.background()
+    }
+
+    pub fn is_group(&self) -> bool {
+        self.layer_type == LayerType::Group
+    }
+
+    pub fn is_tilemap(&self) -> bool {
+        self.layer_type == LayerType::Tilemap
+    }
+}
+
+/// The flags of a [LayerHeader].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LayerFlags(pub u16);
+
+impl LayerFlags {
+    pub fn visible(&self) -> bool {
+        self.0 & constants::LAYER_VISIBLE != 0
+    }
+
+    pub fn editable(&self) -> bool {
+        self.0 & constants::LAYER_EDITABLE != 0
+    }
+
+    pub fn lock_movement(&self) -> bool {
+        self.0 & constants::LAYER_LOCKMOVE != 0
+    }
+
+    pub fn background(&self) -> bool {
+        self.0 & constants::LAYER_BACKGROUND != 0
+    }
+
+    /// Whether linking cels is preferred when copying them to new frames.
+    pub fn continuous(&self) -> bool {
+        self.0 & constants::LAYER_CONTINUOUS != 0
+    }
+
+    /// Whether a group is collapsed in the UI.
+    pub fn collapsed(&self) -> bool {
+        self.0 & constants::LAYER_COLLAPSED != 0
+    }
+
+    /// Whether this is a reference layer, which isn't part of the sprite.
+    pub fn reference(&self) -> bool {
+        self.0 & constants::LAYER_REFERENCE != 0
+    }
+}
+
+impl Parse for LayerFlags {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        Ok(LayerFlags(p.next()?))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LayerType {
+    Image,
+    Group,
+    Tilemap,
+    /// A layer type this crate doesn't know about.
+    Unknown(u16),
+}
+
+impl From<u16> for LayerType {
+    fn from(v: u16) -> Self {
+        match v {
+            constants::ASE_FILE_LAYER_IMAGE => LayerType::Image,
+            constants::ASE_FILE_LAYER_GROUP => LayerType::Group,
+            constants::ASE_FILE_LAYER_TILEMAP => LayerType::Tilemap,
+            v => LayerType::Unknown(v),
+        }
+    }
+}
+
+impl Parse for LayerType {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        Ok(u16::parse(p)?.into())
     }
 }
 
 impl Parse for LayerHeader {
     fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
-        Ok(LayerHeader {
+        let mut layer = LayerHeader {
             flags: p.next()?,
             layer_type: p.next()?,
             child_level: p.next()?,
@@ -90,7 +236,14 @@ impl Parse for LayerHeader {
             opacity: p.next()?,
             _skip: p.next()?,
             name: p.next()?,
-        })
+            tileset_index: None,
+            uuid: None,
+            user_data: UserData::default(),
+        };
+        if layer.is_tilemap() {
+            layer.tileset_index = Some(p.next()?);
+        }
+        Ok(layer)
     }
 }
 
@@ -98,13 +251,73 @@ impl Parse for LayerHeader {
 pub struct Tag {
     pub from: u16,
     pub to: u16,
-    pub anidir: u8,
-    _skip0: Skip<8>,
+    pub anidir: AnimationDirection,
+    /// How many times the tag plays, or 0 to play forever. Each way of a
+    /// ping-pong counts as one time.
+    pub repeat: u16,
+    _skip0: Skip<6>,
     pub r: u8,
     pub g: u8,
     pub b: u8,
     _skip1: Skip<1>,
     pub name: String,
+    pub user_data: UserData,
+}
+
+impl Tag {
+    pub fn user_data(&self) -> &UserData {
+        &self.user_data
+    }
+
+    /// The frames of the tag in the order Aseprite plays them, taking the
+    /// direction and repeat count into account. Ping-pongs don't repeat the
+    /// frame they turn around on.
+    ///
+    /// For tags that play forever this is a single loop, which ends just
+    /// before the animation starts over.
+    pub fn frame_sequence(&self) -> Vec<usize> {
+        let (from, to) = (usize::from(self.from), usize::from(self.to));
+        let forward = |skip_first: bool| (from + usize::from(skip_first))..=to;
+        let backward = |skip_first: bool| (from..=to.saturating_sub(usize::from(skip_first))).rev();
+
+        let starts_reversed = matches!(
+            self.anidir,
+            AnimationDirection::Reverse | AnimationDirection::PingPongReverse
+        );
+        let ping_pong = matches!(
+            self.anidir,
+            AnimationDirection::PingPong | AnimationDirection::PingPongReverse
+        );
+
+        let mut frames = Vec::new();
+        if self.repeat == 0 {
+            if starts_reversed {
+                frames.extend(backward(false));
+            } else {
+                frames.extend(forward(false));
+            }
+            // The way back, without either end.
+            if ping_pong && to > from {
+                if starts_reversed {
+                    frames.extend(from + 1..to);
+                } else {
+                    frames.extend((from + 1..to).rev());
+                }
+            }
+            return frames;
+        }
+
+        for pass in 0..usize::from(self.repeat) {
+            let reversed = starts_reversed != (ping_pong && pass % 2 == 1);
+            let skip_first = ping_pong && pass > 0;
+            if reversed {
+                frames.extend(backward(skip_first));
+            } else {
+                frames.extend(forward(skip_first));
+            }
+        }
+        frames
+    }
 }
 
 impl Parse for Tag {
@@ -113,16 +326,455 @@ impl Parse for Tag {
             from: p.next()?,
             to: p.next()?,
             anidir: p.next()?,
+            repeat: p.next()?,
             _skip0: p.next()?,
             r: p.next()?,
             g: p.next()?,
             b: p.next()?,
             _skip1: p.next()?,
             name: p.next()?,
+            user_data: UserData::default(),
+        })
+    }
+}
+
+/// The order the frames of a [Tag] are played in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AnimationDirection {
+    Forward,
+    Reverse,
+    /// Forward, then back again.
+    PingPong,
+    /// Backwards, then forward again.
+    PingPongReverse,
+    /// A direction this crate doesn't know about.
+    Unknown(u8),
+}
+
+impl From<u8> for AnimationDirection {
+    fn from(v: u8) -> Self {
+        match v {
+            constants::ASE_ANI_DIR_FORWARD => AnimationDirection::Forward,
+            constants::ASE_ANI_DIR_REVERSE => AnimationDirection::Reverse,
+            constants::ASE_ANI_DIR_PING_PONG => AnimationDirection::PingPong,
+            constants::ASE_ANI_DIR_PING_PONG_REVERSE => AnimationDirection::PingPongReverse,
+            v => AnimationDirection::Unknown(v),
+        }
+    }
+}
+
+impl Parse for AnimationDirection {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        Ok(u8::parse(p)?.into())
+    }
+}
+
+/// Extra information about the preceding cel.
+#[derive(Debug)]
+pub struct CelExtra {
+    pub flags: u32,
+    /// The precise position and size of the cel, in sprite pixels. Only
+    /// meaningful if [CelExtra::has_precise_bounds] is true.
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    _skip: Skip<16>,
+}
+
+impl CelExtra {
+    pub fn has_precise_bounds(&self) -> bool {
+        self.flags & constants::ASE_CEL_EXTRA_FLAG_PRECISE_BOUNDS != 0
+    }
+}
+
+impl Parse for CelExtra {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        Ok(CelExtra {
+            flags: p.next()?,
+            x: parse_fixed(p)?,
+            y: parse_fixed(p)?,
+            width: parse_fixed(p)?,
+            height: parse_fixed(p)?,
+            _skip: p.next()?,
         })
     }
 }
 
+// Reads a 16.16 fixed-point number.
+pub(crate) fn parse_fixed<R: Read + Seek>(p: &mut Parser<R>) -> Result<f64, AsepriteError> {
+    Ok(f64::from(i32::parse(p)?) / 65536.0)
+}
+
+/// A file or extension referred to by this file.
+#[derive(Debug)]
+pub struct ExternalFile {
+    /// The ID other chunks use to refer to this file.
+    pub id: u32,
+    pub file_type: u8,
+    _skip: Skip<7>,
+    /// The file name, or the ID of the extension for extensions.
+    pub name: String,
+}
+
+impl ExternalFile {
+    pub fn is_palette(&self) -> bool {
+        self.file_type == constants::ASE_FILE_EXTERNAL_PALETTE
+    }
+
+    pub fn is_tileset(&self) -> bool {
+        self.file_type == constants::ASE_FILE_EXTERNAL_TILESET
+    }
+
+    /// Whether this is an extension that owns properties in user data.
+    pub fn is_properties_extension(&self) -> bool {
+        self.file_type == constants::ASE_FILE_EXTERNAL_PROPERTIES_EXTENSION
+    }
+
+    pub fn is_tile_management_extension(&self) -> bool {
+        self.file_type == constants::ASE_FILE_EXTERNAL_TILE_MANAGEMENT_EXTENSION
+    }
+}
+
+impl Parse for ExternalFile {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        Ok(ExternalFile {
+            id: p.next()?,
+            file_type: p.next()?,
+            _skip: p.next()?,
+            name: p.next()?,
+        })
+    }
+}
+
+/// A reference to a tileset stored in another file.
+#[derive(Debug)]
+pub struct ExternalTileset {
+    pub file_id: u32,
+    pub tileset_id: u32,
+}
+
+/// A set of tiles used by tilemap layers.
+#[derive(Debug)]
+pub struct Tileset {
+    pub id: u32,
+    pub flags: u32,
+    pub tile_count: u32,
+    pub tile_width: u16,
+    pub tile_height: u16,
+    /// The number shown in the UI for the first tile.
+    pub base_index: i16,
+    _skip: Skip<14>,
+    pub name: String,
+    pub external: Option<ExternalTileset>,
+    /// The images of the tiles, if they are embedded in this file. The tile
+    /// with ID 0 is always empty.
+    pub tiles: Vec<Image>,
+    pub user_data: UserData,
+    /// The user data of each tile, up to the last tile that has any.
+    pub tile_user_data: Vec<UserData>,
+}
+
+impl Tileset {
+    pub fn user_data(&self) -> &UserData {
+        &self.user_data
+    }
+
+    /// Returns the user data of the tile with the given ID, if it has any.
+    pub fn tile_user_data(&self, id: u32) -> Option<&UserData> {
+        self.tile_user_data.get(usize::try_from(id).ok()?)
+    }
+
+    /// Returns the image of the tile with the given ID, if it's available.
+    pub fn tile(&self, id: u32) -> Option<&Image> {
+        self.tiles.get(usize::try_from(id).ok()?)
+    }
+}
+
+impl Parse for Tileset {
+    // The tile images depend on the color depth of the file, so they're
+    // left for the caller to read.
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        let mut tileset = Tileset {
+            id: p.next()?,
+            flags: p.next()?,
+            tile_count: p.next()?,
+            tile_width: p.next()?,
+            tile_height: p.next()?,
+            base_index: p.next()?,
+            _skip: p.next()?,
+            name: p.next()?,
+            external: None,
+            tiles: Vec::new(),
+            user_data: UserData::default(),
+            tile_user_data: Vec::new(),
+        };
+        if tileset.flags & constants::ASE_TILESET_FLAG_EXTERNAL_FILE != 0 {
+            tileset.external = Some(ExternalTileset {
+                file_id: p.next()?,
+                tileset_id: p.next()?,
+            });
+        }
+        Ok(tileset)
+    }
+}
+
+/// A single tile in a [TileGrid].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tile {
+    /// The index of the tile in its [Tileset]. The tile with ID 0 is empty.
+    pub id: u32,
+    pub x_flip: bool,
+    pub y_flip: bool,
+    /// Whether the tile is flipped along its top-left to bottom-right
+    /// diagonal. This is applied before the other two flips.
+    pub diagonal_flip: bool,
+}
+
+/// The tiles of a tilemap cel.
+#[derive(Debug, Clone)]
+pub struct TileGrid {
+    /// The width of the grid, in tiles.
+    pub width: u16,
+    /// The height of the grid, in tiles.
+    pub height: u16,
+    /// The position of the top-left corner of the grid on the canvas, in
+    /// pixels.
+    pub x: i16,
+    pub y: i16,
+    /// The ID of the [Tileset] the tiles come from.
+    pub tileset_id: u32,
+    /// The tiles of the grid, row by row.
+    pub tiles: Vec<Tile>,
+}
+
+impl TileGrid {
+    /// Returns the tile at the given position in the grid, in tiles.
+    pub fn tile(&self, x: u16, y: u16) -> Option<Tile> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.tiles
+            .get(y as usize * self.width as usize + x as usize)
+            .copied()
+    }
+
+    /// Parses the body of a compressed tilemap cel, which runs until
+    /// `chunk_end`.
+    pub(crate) fn parse<R: Read + Seek>(
+        p: &mut Parser<R>,
+        chunk_end: usize,
+        x: i16,
+        y: i16,
+        tileset_id: u32,
+    ) -> Result<Self, AsepriteError> {
+        let width: u16 = p.next()?;
+        let height: u16 = p.next()?;
+        let bits_per_tile: u16 = p.next()?;
+        let id_mask: u32 = p.next()?;
+        let x_flip_mask: u32 = p.next()?;
+        let y_flip_mask: u32 = p.next()?;
+        let diagonal_flip_mask: u32 = p.next()?;
+        p.skip(10)?;
+
+        let data = p.next_n(chunk_end - p.position())?;
+        let data = inflate::inflate_bytes_zlib(data).map_err(AsepriteError::CorruptFile)?;
+        let bytes_per_tile = match bits_per_tile {
+            8 | 16 | 32 => usize::from(bits_per_tile / 8),
+            b => {
+                return Err(AsepriteError::Unimplemented(format!(
+                    "unhandled tile size of {} bits. Please open an issue including the file you're attempting to open.",
+                    b
+                )));
+            }
+        };
+        if data.len() != width as usize * height as usize * bytes_per_tile {
+            return Err(AsepriteError::CorruptFile(format!(
+                "tilemap of size {}x{} has {} bytes of tile data",
+                width,
+                height,
+                data.len()
+            )));
+        }
+        let tiles = data
+            .chunks_exact(bytes_per_tile)
+            .map(|t| {
+                let t = t
+                    .iter()
+                    .rev()
+                    .fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+                Tile {
+                    id: t & id_mask,
+                    x_flip: t & x_flip_mask != 0,
+                    y_flip: t & y_flip_mask != 0,
+                    diagonal_flip: t & diagonal_flip_mask != 0,
+                }
+            })
+            .collect();
+
+        Ok(TileGrid {
+            width,
+            height,
+            x,
+            y,
+            tileset_id,
+            tiles,
+        })
+    }
+}
+
+/// A single color in a [Palette].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaletteEntry {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+    pub name: Option<String>,
+}
+
+/// The color palette of the sprite. Indexed images refer to colors by their
+/// position in this palette.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Palette {
+    pub entries: Vec<PaletteEntry>,
+}
+
+impl Palette {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the color at index `i`, if the palette has one.
+    pub fn get(&self, i: usize) -> Option<&PaletteEntry> {
+        self.entries.get(i)
+    }
+
+    /// Applies a palette chunk to this palette. Palette chunks only contain the
+    /// range of entries that changed, so we update in place rather than
+    /// replacing the whole thing.
+    pub(crate) fn apply_chunk<R: Read + Seek>(
+        &mut self,
+        p: &mut Parser<R>,
+    ) -> Result<(), AsepriteError> {
+        let size: u32 = p.next()?;
+        let first: u32 = p.next()?;
+        let last: u32 = p.next()?;
+        p.skip(8)?;
+
+        if first > last || last >= size {
+            return Err(AsepriteError::CorruptFile(format!(
+                "palette range {}..={} doesn't fit in a palette of {} colors",
+                first, last, size
+            )));
+        }
+
+        self.entries
+            .resize(size.try_into()?, PaletteEntry::default());
+        for i in first..=last {
+            let flags: u16 = p.next()?;
+            let mut entry = PaletteEntry {
+                r: p.next()?,
+                g: p.next()?,
+                b: p.next()?,
+                a: p.next()?,
+                name: None,
+            };
+            if flags & constants::ASE_PALETTE_FLAG_HAS_NAME != 0 {
+                entry.name = Some(p.next()?);
+            }
+            let i: usize = i.try_into()?;
+            self.entries[i] = entry;
+        }
+
+        Ok(())
+    }
+
+    /// Applies one of the palette chunks from older versions of Aseprite,
+    /// which are made of packets of consecutive colors. `six_bit` chunks
+    /// have color components from 0 to 63.
+    pub(crate) fn apply_old_chunk<R: Read + Seek>(
+        &mut self,
+        p: &mut Parser<R>,
+        six_bit: bool,
+    ) -> Result<(), AsepriteError> {
+        let npackets: u16 = p.next()?;
+        let mut i = 0;
+        for _ in 0..npackets {
+            let skip: u8 = p.next()?;
+            let ncolors: u8 = p.next()?;
+            let ncolors = if ncolors == 0 { 256 } else { ncolors.into() };
+            i += usize::from(skip);
+            if self.entries.len() < i + ncolors {
+                self.entries.resize(i + ncolors, PaletteEntry::default());
+            }
+            for _ in 0..ncolors {
+                let mut rgb = [p.next()?, p.next()?, p.next()?];
+                if six_bit {
+                    rgb = rgb.map(|c: u8| (c << 2) | (c >> 4));
+                }
+                self.entries[i] = PaletteEntry {
+                    r: rgb[0],
+                    g: rgb[1],
+                    b: rgb[2],
+                    a: 255,
+                    name: None,
+                };
+                i += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A selection mask, from older versions of Aseprite.
+#[derive(Debug)]
+pub struct Mask {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+    _skip: Skip<8>,
+    pub name: String,
+    /// One bit per pixel, row by row, with the most significant bit of each
+    /// byte first. Each row starts on a new byte.
+    pub bitmap: Vec<u8>,
+}
+
+impl Mask {
+    /// Returns whether the pixel at the given position, relative to the
+    /// mask, is selected.
+    pub fn get(&self, x: u16, y: u16) -> bool {
+        if x >= self.width || y >= self.height {
+            return false;
+        }
+        let stride = (self.width as usize).div_ceil(8);
+        let byte = self.bitmap[y as usize * stride + x as usize / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
+
+impl Parse for Mask {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        let mut mask = Mask {
+            x: p.next()?,
+            y: p.next()?,
+            width: p.next()?,
+            height: p.next()?,
+            _skip: p.next()?,
+            name: p.next()?,
+            bitmap: Vec::new(),
+        };
+        let len = mask.height as usize * (mask.width as usize).div_ceil(8);
+        mask.bitmap = p.next_n(len)?.to_vec();
+        Ok(mask)
+    }
+}
+
 /// A keyframe for a [Slice].
 #[derive(Debug)]
 pub struct SliceKey {
@@ -139,6 +791,21 @@ pub struct Slice {
     pub user_data: UserData,
 }
 
+impl Slice {
+    pub fn user_data(&self) -> &UserData {
+        &self.user_data
+    }
+
+    /// The key in effect at frame `frame`: each key holds from its frame
+    /// until the next key. Returns None before the first key.
+    pub fn key_at(&self, frame: usize) -> Option<&SliceKey> {
+        self.keys
+            .iter()
+            .filter(|k| k.frame as usize <= frame)
+            .max_by_key(|k| k.frame)
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct UserData {
     pub string: String,
@@ -146,16 +813,124 @@ pub struct UserData {
     pub g: u8,
     pub b: u8,
     pub a: u8,
+    /// Properties maps keyed by the ID of the extension that owns them. The
+    /// properties set by the user have the key 0. A map with a property of a
+    /// type this crate doesn't know is left out, along with the maps after
+    /// it.
+    pub properties: BTreeMap<u32, Properties>,
 }
 
-#[derive(Debug, Default)]
+impl UserData {
+    /// Whether there's no text, color or properties.
+    pub fn is_empty(&self) -> bool {
+        self.string.is_empty()
+            && (self.r, self.g, self.b, self.a) == (0, 0, 0, 0)
+            && self.properties.values().all(|p| p.is_empty())
+    }
+
+    /// The properties set by the user. This is empty if there aren't any.
+    pub fn properties(&self) -> &Properties {
+        static EMPTY: Properties = Properties(BTreeMap::new());
+        self.properties.get(&0).unwrap_or(&EMPTY)
+    }
+
+    /// The properties owned by the extension with the given ID, as listed in
+    /// the external files of the sprite.
+    pub fn extension_properties(&self, id: u32) -> Option<&Properties> {
+        self.properties.get(&id).filter(|_| id != 0)
+    }
+}
+
+impl Parse for UserData {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        let flags: u32 = p.next()?;
+        let mut user_data = UserData::default();
+        if flags & constants::ASE_USER_DATA_FLAG_HAS_TEXT != 0 {
+            user_data.string = p.next()?;
+        }
+        if flags & constants::ASE_USER_DATA_FLAG_HAS_COLOR != 0 {
+            user_data.r = p.next()?;
+            user_data.g = p.next()?;
+            user_data.b = p.next()?;
+            user_data.a = p.next()?;
+        }
+        if flags & constants::ASE_USER_DATA_FLAG_HAS_PROPERTIES != 0 {
+            let start = p.position();
+            let size: u32 = p.next()?;
+            let end = start + usize::try_from(size)?;
+            let nmaps: u32 = p.next()?;
+            for _ in 0..nmaps {
+                let key: u32 = p.next()?;
+                match p.next() {
+                    Ok(properties) => {
+                        user_data.properties.insert(key, properties);
+                    }
+                    // Maps don't say how long they are, so the ones after a
+                    // property we don't understand can't be found either.
+                    Err(AsepriteError::Unimplemented(_)) => {
+                        p.advance_to(end)?;
+                        break;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(user_data)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Rect {
-    pub x: u32,
-    pub y: u32,
+    pub x: i32,
+    pub y: i32,
     pub w: u32,
     pub h: u32,
 }
 
+impl Rect {
+    /// Whether the point `(x, y)` is inside the rectangle.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let (x, y) = (i64::from(x), i64::from(y));
+        x >= i64::from(self.x) && y >= i64::from(self.y) && x < self.right() && y < self.bottom()
+    }
+
+    /// The overlap of the two rectangles, or None if they don't overlap.
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= i64::from(x) || bottom <= i64::from(y) {
+            return None;
+        }
+        Some(Rect {
+            x,
+            y,
+            w: (right - i64::from(x)) as u32,
+            h: (bottom - i64::from(y)) as u32,
+        })
+    }
+
+    /// The rectangle moved by `(dx, dy)`, or None if its position would
+    /// overflow.
+    pub fn translate(&self, dx: i32, dy: i32) -> Option<Rect> {
+        Some(Rect {
+            x: self.x.checked_add(dx)?,
+            y: self.y.checked_add(dy)?,
+            ..*self
+        })
+    }
+
+    // These can be past i32::MAX, so they're i64.
+    fn right(&self) -> i64 {
+        i64::from(self.x) + i64::from(self.w)
+    }
+
+    fn bottom(&self) -> i64 {
+        i64::from(self.y) + i64::from(self.h)
+    }
+}
+
 impl Parse for Rect {
     fn parse<R>(p: &mut Parser<R>) -> Result<Self, AsepriteError>
     where
@@ -170,10 +945,10 @@ impl Parse for Rect {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Point {
-    pub x: u32,
-    pub y: u32,
+    pub x: i32,
+    pub y: i32,
 }
 
 impl Parse for Point {
@@ -31,7 +31,7 @@ impl_parse!(i64);
 impl Parse for String {
     fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
         // Strings in Aseprite files are always length-prefixed with a u16.
-        let len = u16::parse(p)?.try_into()?;
+        let len = u16::parse(p)?.into();
         Ok(String::from_utf8(p.next_n(len)?.to_vec())?)
     }
 }
@@ -0,0 +1,219 @@
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek},
+};
+
+use crate::{
+    metadata::parse_fixed,
+    parser::{Parse, Parser},
+    AsepriteError,
+};
+
+/// A single value in a [Properties] map.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int8(i8),
+    UInt8(u8),
+    Int16(i16),
+    UInt16(u16),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    /// A 16.16 fixed-point number.
+    Fixed(f64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Point {
+        x: i32,
+        y: i32,
+    },
+    Size {
+        w: i32,
+        h: i32,
+    },
+    Rect {
+        x: i32,
+        y: i32,
+        w: i32,
+        h: i32,
+    },
+    Vector(Vec<PropertyValue>),
+    Map(Properties),
+    Uuid([u8; 16]),
+}
+
+impl PropertyValue {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PropertyValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of any of the integer types, if it fits in an i64.
+    pub fn as_int(&self) -> Option<i64> {
+        match *self {
+            PropertyValue::Int8(v) => Some(v.into()),
+            PropertyValue::UInt8(v) => Some(v.into()),
+            PropertyValue::Int16(v) => Some(v.into()),
+            PropertyValue::UInt16(v) => Some(v.into()),
+            PropertyValue::Int32(v) => Some(v.into()),
+            PropertyValue::UInt32(v) => Some(v.into()),
+            PropertyValue::Int64(v) => Some(v),
+            PropertyValue::UInt64(v) => v.try_into().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of any of the number types as a float. Integers are
+    /// converted too.
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            PropertyValue::Fixed(v) | PropertyValue::Double(v) => Some(v),
+            PropertyValue::Float(v) => Some(v.into()),
+            PropertyValue::UInt64(v) => Some(v as f64),
+            _ => self.as_int().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PropertyValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_vector(&self) -> Option<&[PropertyValue]> {
+        match self {
+            PropertyValue::Vector(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&Properties> {
+        match self {
+            PropertyValue::Map(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    fn parse<R: Read + Seek>(p: &mut Parser<R>, ty: u16) -> Result<Self, AsepriteError> {
+        Ok(match ty {
+            0x0001 => PropertyValue::Bool(u8::parse(p)? != 0),
+            0x0002 => PropertyValue::Int8(p.next()?),
+            0x0003 => PropertyValue::UInt8(p.next()?),
+            0x0004 => PropertyValue::Int16(p.next()?),
+            0x0005 => PropertyValue::UInt16(p.next()?),
+            0x0006 => PropertyValue::Int32(p.next()?),
+            0x0007 => PropertyValue::UInt32(p.next()?),
+            0x0008 => PropertyValue::Int64(p.next()?),
+            0x0009 => PropertyValue::UInt64(p.next()?),
+            0x000A => PropertyValue::Fixed(parse_fixed(p)?),
+            0x000B => PropertyValue::Float(f32::from_bits(p.next()?)),
+            0x000C => PropertyValue::Double(f64::from_bits(p.next()?)),
+            0x000D => PropertyValue::String(p.next()?),
+            0x000E => PropertyValue::Point {
+                x: p.next()?,
+                y: p.next()?,
+            },
+            0x000F => PropertyValue::Size {
+                w: p.next()?,
+                h: p.next()?,
+            },
+            0x0010 => PropertyValue::Rect {
+                x: p.next()?,
+                y: p.next()?,
+                w: p.next()?,
+                h: p.next()?,
+            },
+            0x0011 => {
+                let n: u32 = p.next()?;
+                let element_type: u16 = p.next()?;
+                let mut elements = Vec::new();
+                for _ in 0..n {
+                    // A type of 0 means every element has its own type.
+                    let ty = if element_type == 0 {
+                        p.next()?
+                    } else {
+                        element_type
+                    };
+                    elements.push(PropertyValue::parse(p, ty)?);
+                }
+                PropertyValue::Vector(elements)
+            }
+            0x0012 => PropertyValue::Map(p.next()?),
+            0x0013 => PropertyValue::Uuid(p.next_n(16)?.try_into()?),
+            ty => {
+                return Err(AsepriteError::Unimplemented(format!(
+                    "unhandled property type 0x{:x}. Please open an issue including the file you're attempting to open.",
+                    ty
+                )));
+            }
+        })
+    }
+}
+
+/// A map of named values attached to [UserData](crate::UserData).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Properties(pub BTreeMap<String, PropertyValue>);
+
+impl Properties {
+    pub fn get(&self, name: &str) -> Option<&PropertyValue> {
+        self.0.get(name)
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get(name)?.as_bool()
+    }
+
+    /// Returns the named property if it has an integer type.
+    pub fn get_int(&self, name: &str) -> Option<i64> {
+        self.get(name)?.as_int()
+    }
+
+    /// Returns the named property if it has a number type, converted to a
+    /// float.
+    pub fn get_float(&self, name: &str) -> Option<f64> {
+        self.get(name)?.as_float()
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get(name)?.as_str()
+    }
+
+    pub fn get_vector(&self, name: &str) -> Option<&[PropertyValue]> {
+        self.get(name)?.as_vector()
+    }
+
+    pub fn get_map(&self, name: &str) -> Option<&Properties> {
+        self.get(name)?.as_map()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PropertyValue)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+impl Parse for Properties {
+    fn parse<R: Read + Seek>(p: &mut Parser<R>) -> Result<Self, AsepriteError> {
+        let n: u32 = p.next()?;
+        let mut properties = BTreeMap::new();
+        for _ in 0..n {
+            let name: String = p.next()?;
+            let ty: u16 = p.next()?;
+            properties.insert(name, PropertyValue::parse(p, ty)?);
+        }
+        Ok(Properties(properties))
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{AsepriteError, AsepriteFile, BlendMode, Image, LayerHeader};
+
+/// Picks out layers for [RenderOptions].
+pub enum LayerFilter<'a> {
+    /// The layer at this index of [AsepriteFile::layers].
+    Index(usize),
+    /// Every layer with this name.
+    Name(&'a str),
+    /// The layer at this path, as in [AsepriteFile::layer_by_path].
+    Path(&'a str),
+    /// Every layer for which this returns true, given its index and header.
+    Predicate(&'a dyn Fn(usize, &LayerHeader) -> bool),
+}
+
+impl LayerFilter<'_> {
+    fn matches(&self, file: &AsepriteFile, layer: usize) -> bool {
+        match self {
+            LayerFilter::Index(i) => *i == layer,
+            LayerFilter::Name(name) => file.layers[layer].name == *name,
+            LayerFilter::Path(path) => file.layer_path(layer).as_deref() == Some(*path),
+            LayerFilter::Predicate(f) => f(layer, &file.layers[layer]),
+        }
+    }
+}
+
+/// Which layers [AsepriteFile::render_frame] draws, and how. By default this
+/// draws the frame like [AsepriteFile::frame_image].
+///
+/// Selecting or excluding a group applies to everything in it. When several
+/// overrides match a layer, the last one wins.
+#[derive(Default)]
+pub struct RenderOptions<'a> {
+    only: Vec<LayerFilter<'a>>,
+    exclude: Vec<LayerFilter<'a>>,
+    visibility: Vec<(LayerFilter<'a>, bool)>,
+    opacity: Vec<(LayerFilter<'a>, u8)>,
+}
+
+impl<'a> RenderOptions<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only draws the layers matching `filter` (or the filters from other
+    /// calls to `only`). Hidden layers still aren't drawn.
+    pub fn only(mut self, filter: LayerFilter<'a>) -> Self {
+        self.only.push(filter);
+        self
+    }
+
+    /// Never draws the layers matching `filter`.
+    pub fn exclude(mut self, filter: LayerFilter<'a>) -> Self {
+        self.exclude.push(filter);
+        self
+    }
+
+    /// Draws the layers matching `filter` even if they're hidden.
+    pub fn show(mut self, filter: LayerFilter<'a>) -> Self {
+        self.visibility.push((filter, true));
+        self
+    }
+
+    /// Treats the layers matching `filter` as hidden.
+    pub fn hide(mut self, filter: LayerFilter<'a>) -> Self {
+        self.visibility.push((filter, false));
+        self
+    }
+
+    /// Draws the layers matching `filter` with this opacity instead of their
+    /// own.
+    pub fn opacity(mut self, filter: LayerFilter<'a>, opacity: u8) -> Self {
+        self.opacity.push((filter, opacity));
+        self
+    }
+
+    fn visible(&self, file: &AsepriteFile, layer: usize) -> bool {
+        self.visibility
+            .iter()
+            .rev()
+            .find(|(f, _)| f.matches(file, layer))
+            .map_or(file.layers[layer].visible(), |&(_, v)| v)
+    }
+
+    fn layer_opacity(&self, file: &AsepriteFile, layer: usize) -> Option<u8> {
+        self.opacity
+            .iter()
+            .rev()
+            .find(|(f, _)| f.matches(file, layer))
+            .map(|&(_, o)| o)
+    }
+}
+
+impl AsepriteFile {
+    // The opacity of the layer at index `layer`, or 255 if the header flags
+    // say that the opacity of layers like it wasn't saved.
+    pub(crate) fn saved_opacity(&self, layer: usize) -> u8 {
+        let l = &self.layers[layer];
+        let valid = if l.is_group() {
+            self.header.flags.group_opacity_valid()
+        } else {
+            self.header.flags.layer_opacity_valid()
+        };
+        if valid {
+            l.opacity
+        } else {
+            255
+        }
+    }
+
+    /// Composites frame `frame` from the layers chosen by `options`.
+    pub fn render_frame(
+        &self,
+        frame: usize,
+        options: &RenderOptions,
+    ) -> Result<Image, AsepriteError> {
+        self.try_frame(frame)?;
+        let mut image = Image::new(self.header.width, self.header.height);
+        self.composite(frame, None, options, options.only.is_empty(), &mut image)?;
+        Ok(image)
+    }
+
+    // Draws the children of the group `parent` (or the top-level layers, if
+    // it's None) onto `into`. `selected` says whether a group containing these
+    // layers was selected.
+    //
+    // The header flags say whether the opacity of layers and groups was saved.
+    // Without them, layers are opaque, and groups aren't there at all: their
+    // children are drawn straight onto the layers below. Otherwise groups are
+    // rendered in isolation and then drawn with their own opacity and blend
+    // mode, like any other layer. Groups given an opacity by `options` are
+    // always isolated.
+    //
+    // A cel's z-index moves it among its siblings: it's drawn at its layer's
+    // position plus the z-index, after any layer already there.
+    fn composite(
+        &self,
+        frame: usize,
+        parent: Option<usize>,
+        options: &RenderOptions,
+        selected: bool,
+        into: &mut Image,
+    ) -> Result<(), AsepriteError> {
+        let mut children: Vec<_> = self
+            .layer_children(parent)
+            .enumerate()
+            .map(|(pos, i)| {
+                let z = self.frames[frame]
+                    .cel(i)
+                    .map_or(0, |c| isize::from(c.z_index));
+                ((pos as isize + z, z), i)
+            })
+            .collect();
+        children.sort_by_key(|&(order, _)| order);
+        for (_, i) in children {
+            if !options.visible(self, i) || options.exclude.iter().any(|f| f.matches(self, i)) {
+                continue;
+            }
+            let selected = selected || options.only.iter().any(|f| f.matches(self, i));
+            let l = &self.layers[i];
+            let opacity = options.layer_opacity(self, i);
+            if !l.is_group() {
+                if selected {
+                    let opacity = opacity.unwrap_or(self.saved_opacity(i));
+                    into.draw(0, 0, &self.layer_image(frame, i)?, opacity, l.blend_mode)?;
+                }
+            } else if self.header.flags.group_opacity_valid() {
+                let mut group = Image::new(self.header.width, self.header.height);
+                self.composite(frame, Some(i), options, selected, &mut group)?;
+                into.draw(
+                    0,
+                    0,
+                    &group,
+                    opacity.unwrap_or(self.saved_opacity(i)),
+                    l.blend_mode,
+                )?;
+            } else if let Some(opacity) = opacity {
+                let mut group = Image::new(self.header.width, self.header.height);
+                self.composite(frame, Some(i), options, selected, &mut group)?;
+                into.draw(0, 0, &group, opacity, BlendMode::Normal)?;
+            } else {
+                self.composite(frame, Some(i), options, selected, into)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+use std::{fmt::Write, fs, path::Path};
+
+use crate::{AsepriteError, AsepriteFile, Image, Tileset};
+
+// Tiled stores tile flips in the top bits of each tile's global ID.
+const TILED_FLIPPED_HORIZONTALLY: u32 = 0x8000_0000;
+const TILED_FLIPPED_VERTICALLY: u32 = 0x4000_0000;
+const TILED_FLIPPED_DIAGONALLY: u32 = 0x2000_0000;
+
+/// A frame of an [AsepriteFile] converted to a Tiled map. Nothing is written
+/// to disk until [TiledExport::write] is called.
+#[derive(Debug)]
+pub struct TiledExport {
+    /// The file name of the map.
+    pub map_file: String,
+    /// The contents of the `.tmx` map.
+    pub map: String,
+    pub tilesets: Vec<TiledTileset>,
+    /// The PNG images of image layers, along with their file names.
+    pub images: Vec<(String, Vec<u8>)>,
+}
+
+/// A tileset converted to a Tiled external tileset.
+#[derive(Debug)]
+pub struct TiledTileset {
+    /// The file name of the `.tsx` tileset.
+    pub tsx_file: String,
+    /// The contents of the `.tsx` tileset.
+    pub tsx: String,
+    /// The file name of the tileset image.
+    pub image_file: String,
+    /// The PNG tileset image, with the tiles stacked vertically.
+    pub image: Vec<u8>,
+}
+
+impl TiledExport {
+    /// Writes the map and every file it references into `dir`.
+    pub fn write<P: AsRef<Path>>(&self, dir: P) -> Result<(), AsepriteError> {
+        let dir = dir.as_ref();
+        fs::write(dir.join(&self.map_file), &self.map)?;
+        for tileset in &self.tilesets {
+            fs::write(dir.join(&tileset.tsx_file), &tileset.tsx)?;
+            fs::write(dir.join(&tileset.image_file), &tileset.image)?;
+        }
+        for (name, image) in &self.images {
+            fs::write(dir.join(name), image)?;
+        }
+        Ok(())
+    }
+}
+
+impl AsepriteFile {
+    /// Converts frame `frame` to a Tiled map, with files named after `name`.
+    ///
+    /// Tilemap layers become tile layers, groups become group layers and
+    /// other layers become image layers. The slices of the frame become an
+    /// object layer named "slices".
+    ///
+    /// The map grid has to line up with the tiles of every tileset, so its
+    /// cells are the largest size that divides all of their tile sizes. Tiles
+    /// bigger than that cover several cells, and are placed by their
+    /// bottom-left corner like Tiled expects. Without tilesets, the map uses
+    /// the grid of the sprite.
+    pub fn to_tiled(&self, frame: usize, name: &str) -> Result<TiledExport, AsepriteError> {
+        let f = self.try_frame(frame)?;
+        let (tw, th) = if self.tilesets.is_empty() {
+            (self.header.grid_width, self.header.grid_height)
+        } else {
+            self.tilesets.iter().fold((0, 0), |(w, h), t| {
+                (gcd(w, t.tile_width), gcd(h, t.tile_height))
+            })
+        };
+        if tw == 0 || th == 0 {
+            return Err(AsepriteError::Unimplemented(
+                "can't export a map with an empty tile size".into(),
+            ));
+        }
+        let map_w = self.header.width.div_ceil(tw);
+        let map_h = self.header.height.div_ceil(th);
+
+        let mut export = TiledExport {
+            map_file: format!("{}.tmx", name),
+            map: String::new(),
+            tilesets: Vec::new(),
+            images: Vec::new(),
+        };
+
+        // Tile 0 of an Aseprite tileset is always empty, but we still export
+        // it so that tile IDs line up with global IDs. Tilesets without
+        // embedded tiles have no image to export, but keep their IDs.
+        let mut body = String::new();
+        let mut first_gids = Vec::new();
+        let mut next_gid = 1;
+        for tileset in &self.tilesets {
+            first_gids.push(next_gid);
+            if !tileset.tiles.is_empty() {
+                let t = tiled_tileset(tileset, name)?;
+                writeln!(
+                    body,
+                    r#" <tileset firstgid="{}" source="{}"/>"#,
+                    next_gid,
+                    escape(&t.tsx_file)
+                )?;
+                export.tilesets.push(t);
+            }
+            next_gid += tileset.tile_count;
+        }
+
+        let mut next_id = 1;
+        let mut stack = vec![self.layer_children(None).collect::<Vec<_>>()];
+        let mut depth = 0;
+        while let Some(layers) = stack.last_mut() {
+            if layers.is_empty() {
+                stack.pop();
+                if depth > 0 {
+                    depth -= 1;
+                    writeln!(body, "{}</group>", indent(depth))?;
+                }
+                continue;
+            }
+            let i = layers.remove(0);
+            let l = &self.layers[i];
+            let opacity = self.saved_opacity(i);
+            let attrs = format!(
+                r#"id="{}" name="{}"{}{}"#,
+                next_id,
+                escape(&l.name),
+                if l.visible() { "" } else { r#" visible="0""# },
+                if opacity == 255 {
+                    String::new()
+                } else {
+                    format!(r#" opacity="{:.3}""#, f64::from(opacity) / 255.0)
+                },
+            );
+            next_id += 1;
+
+            if l.is_group() {
+                writeln!(body, "{}<group {}>", indent(depth), attrs)?;
+                depth += 1;
+                stack.push(self.layer_children(Some(i)).collect());
+            } else if l.is_tilemap() {
+                let mut data = vec![0u32; map_w as usize * map_h as usize];
+                let mut offset = (0, 0);
+                if let Some(grid) = f.tile_grid(i) {
+                    let tileset = self.tilesets.iter().position(|t| t.id == grid.tileset_id);
+                    let first_gid = tileset.map_or(1, |t| first_gids[t]);
+                    // How many cells of the map each tile covers.
+                    let (sx, sy) = tileset
+                        .map(|t| &self.tilesets[t])
+                        .filter(|t| t.tile_width > 0 && t.tile_height > 0)
+                        .map_or((1, 1), |t| {
+                            (i32::from(t.tile_width / tw), i32::from(t.tile_height / th))
+                        });
+                    // Tiled layers are aligned to the map grid, so anything
+                    // left over goes into the layer offset.
+                    let col = i32::from(grid.x).div_euclid(tw.into());
+                    let row = i32::from(grid.y).div_euclid(th.into());
+                    offset = (
+                        i32::from(grid.x) - col * i32::from(tw),
+                        i32::from(grid.y) - row * i32::from(th),
+                    );
+                    for ty in 0..grid.height {
+                        for tx in 0..grid.width {
+                            let tile = grid.tile(tx, ty).expect("in bounds");
+                            let mx = col + i32::from(tx) * sx;
+                            let my = row + i32::from(ty) * sy + sy - 1;
+                            if tile.id == 0
+                                || mx < 0
+                                || my < 0
+                                || mx >= map_w.into()
+                                || my >= map_h.into()
+                            {
+                                continue;
+                            }
+                            let mut gid = first_gid + tile.id;
+                            if tile.x_flip {
+                                gid |= TILED_FLIPPED_HORIZONTALLY;
+                            }
+                            if tile.y_flip {
+                                gid |= TILED_FLIPPED_VERTICALLY;
+                            }
+                            if tile.diagonal_flip {
+                                gid |= TILED_FLIPPED_DIAGONALLY;
+                            }
+                            data[my as usize * map_w as usize + mx as usize] = gid;
+                        }
+                    }
+                }
+
+                write!(
+                    body,
+                    r#"{}<layer {} width="{}" height="{}""#,
+                    indent(depth),
+                    attrs,
+                    map_w,
+                    map_h
+                )?;
+                if offset != (0, 0) {
+                    write!(body, r#" offsetx="{}" offsety="{}""#, offset.0, offset.1)?;
+                }
+                writeln!(body, ">")?;
+                writeln!(body, r#"{} <data encoding="csv">"#, indent(depth))?;
+                let rows: Vec<_> = data
+                    .chunks(map_w.into())
+                    .map(|row| {
+                        row.iter()
+                            .map(|gid| gid.to_string())
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    })
+                    .collect();
+                writeln!(body, "{}", rows.join(",\n"))?;
+                writeln!(body, "{} </data>", indent(depth))?;
+                writeln!(body, "{}</layer>", indent(depth))?;
+            } else {
+                let image = self.layer_image(frame, i)?;
+                let file = format!("{}_layer{}.png", name, i);
+                writeln!(body, "{}<imagelayer {}>", indent(depth), attrs)?;
+                writeln!(
+                    body,
+                    r#"{} <image source="{}" width="{}" height="{}"/>"#,
+                    indent(depth),
+                    escape(&file),
+                    image.width,
+                    image.height
+                )?;
+                writeln!(body, "{}</imagelayer>", indent(depth))?;
+                export.images.push((file, encode_png(&image)?));
+            }
+        }
+
+        let mut next_object_id = 1;
+        if !self.slices.is_empty() {
+            writeln!(body, r#" <objectgroup id="{}" name="slices">"#, next_id)?;
+            next_id += 1;
+            for (slice, key) in self.slices_at(frame) {
+                writeln!(
+                    body,
+                    r#"  <object id="{}" name="{}" x="{}" y="{}" width="{}" height="{}"/>"#,
+                    next_object_id,
+                    escape(&slice.name),
+                    key.bounds.x,
+                    key.bounds.y,
+                    key.bounds.w,
+                    key.bounds.h
+                )?;
+                next_object_id += 1;
+            }
+            writeln!(body, " </objectgroup>")?;
+        }
+
+        let map = &mut export.map;
+        writeln!(map, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            map,
+            r#"<map version="1.10" orientation="orthogonal" renderorder="right-down" width="{}" height="{}" tilewidth="{}" tileheight="{}" infinite="0" nextlayerid="{}" nextobjectid="{}">"#,
+            map_w, map_h, tw, th, next_id, next_object_id
+        )?;
+        map.push_str(&body);
+        writeln!(map, "</map>")?;
+
+        Ok(export)
+    }
+}
+
+fn tiled_tileset(tileset: &Tileset, name: &str) -> Result<TiledTileset, AsepriteError> {
+    let file_name = format!("{}_tileset{}", name, tileset.id);
+    let (tw, th) = (tileset.tile_width, tileset.tile_height);
+
+    let sheet: Vec<u8> = tileset
+        .tiles
+        .iter()
+        .flat_map(|t| t.data.iter().copied())
+        .collect();
+    let sheet_height = th as usize * tileset.tiles.len();
+
+    let mut tsx = String::new();
+    writeln!(tsx, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        tsx,
+        r#"<tileset version="1.10" name="{}" tilewidth="{}" tileheight="{}" tilecount="{}" columns="1">"#,
+        escape(&tileset.name),
+        tw,
+        th,
+        tileset.tiles.len()
+    )?;
+    writeln!(
+        tsx,
+        r#" <image source="{}.png" width="{}" height="{}"/>"#,
+        escape(&file_name),
+        tw,
+        sheet_height
+    )?;
+    writeln!(tsx, "</tileset>")?;
+
+    Ok(TiledTileset {
+        tsx_file: format!("{}.tsx", file_name),
+        tsx,
+        image_file: format!("{}.png", file_name),
+        image: encode_rgba_png(tw.into(), sheet_height.try_into()?, &sheet)?,
+    })
+}
+
+fn encode_png(image: &Image) -> Result<Vec<u8>, AsepriteError> {
+    encode_rgba_png(image.width.into(), image.height.into(), &image.data)
+}
+
+fn encode_rgba_png(width: u32, height: u32, data: &[u8]) -> Result<Vec<u8>, AsepriteError> {
+    let mut buf = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buf, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+    Ok(buf)
+}
+
+fn gcd(a: u16, b: u16) -> u16 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn indent(depth: usize) -> String {
+    " ".repeat(depth + 1)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,84 @@
+use crate::{AsepriteFile, Tag};
+
+// All times are in milliseconds, and add up frame durations as u32 so that
+// long animations don't overflow.
+impl AsepriteFile {
+    /// How long frame `frame` is shown for, in milliseconds. Frames with a
+    /// duration of 0 use the speed from the file header instead, like old
+    /// files expect.
+    pub fn frame_duration(&self, frame: usize) -> Option<u16> {
+        let duration = self.frames.get(frame)?.duration;
+        Some(if duration == 0 {
+            self.header.speed
+        } else {
+            duration
+        })
+    }
+
+    /// How long it takes to play every frame once.
+    pub fn total_duration(&self) -> u32 {
+        (0..self.frames.len())
+            .filter_map(|f| self.frame_duration(f))
+            .map(u32::from)
+            .sum()
+    }
+
+    /// When frame `frame` starts, counting from the start of the first frame.
+    pub fn frame_start_time(&self, frame: usize) -> Option<u32> {
+        self.frame_duration(frame)?;
+        Some(
+            (0..frame)
+                .filter_map(|f| self.frame_duration(f))
+                .map(u32::from)
+                .sum(),
+        )
+    }
+
+    /// The frame shown `ms` milliseconds after the start of the first frame,
+    /// or None once every frame has been shown.
+    pub fn frame_at_time(&self, ms: u32) -> Option<usize> {
+        frame_at_time(
+            (0..self.frames.len()).filter_map(|f| Some((f, self.frame_duration(f)?))),
+            ms,
+        )
+    }
+
+    /// How long it takes to play `tag`. For tags that play forever, this is
+    /// the length of one loop.
+    pub fn tag_duration(&self, tag: &Tag) -> u32 {
+        self.tag_frames(tag)
+            .iter()
+            .map(|&(_, d)| u32::from(d))
+            .sum()
+    }
+
+    /// When frame `frame` is first shown while playing `tag`, counting from
+    /// the start of the tag. Returns None if the tag doesn't show it.
+    pub fn tag_frame_start_time(&self, tag: &Tag, frame: usize) -> Option<u32> {
+        let mut start = 0;
+        for (f, duration) in self.tag_frames(tag) {
+            if f == frame {
+                return Some(start);
+            }
+            start += u32::from(duration);
+        }
+        None
+    }
+
+    /// The frame shown `ms` milliseconds after `tag` starts playing, or None
+    /// once it has finished. See [AsepriteFile::tag_duration].
+    pub fn tag_frame_at_time(&self, tag: &Tag, ms: u32) -> Option<usize> {
+        frame_at_time(self.tag_frames(tag), ms)
+    }
+}
+
+fn frame_at_time(frames: impl IntoIterator<Item = (usize, u16)>, ms: u32) -> Option<usize> {
+    let mut end = 0;
+    for (frame, duration) in frames {
+        end += u32::from(duration);
+        if ms < end {
+            return Some(frame);
+        }
+    }
+    None
+}